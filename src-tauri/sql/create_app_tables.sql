@@ -0,0 +1,38 @@
+CREATE TABLE IF NOT EXISTS bookmarks (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    path TEXT NOT NULL,
+    icon TEXT,
+    group_name TEXT,
+    position INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS visits (
+    path TEXT PRIMARY KEY,
+    filetype TEXT NOT NULL,
+    rank REAL NOT NULL,
+    last_accessed INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sort_modes (
+    path TEXT PRIMARY KEY,
+    mode TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS disk_usage (
+    path TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    items INTEGER NOT NULL,
+    scanned INTEGER NOT NULL,
+    children TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS file_hashes (
+    device INTEGER NOT NULL,
+    inode INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    modified INTEGER NOT NULL,
+    partial TEXT NOT NULL,
+    full TEXT,
+    PRIMARY KEY (device, inode, size, modified)
+);
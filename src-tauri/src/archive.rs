@@ -0,0 +1,791 @@
+use super::{
+    jobs::{Job, JobProgress, JobReader},
+    mime, platform, CurrentDirError, FileData, FileType,
+};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use xz2::read::XzDecoder;
+use zip::ZipArchive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+const SUFFIXES: [(&str, ArchiveKind); 8] = [
+    (".zip", ArchiveKind::Zip),
+    (".tar", ArchiveKind::Tar),
+    (".tar.gz", ArchiveKind::TarGz),
+    (".tgz", ArchiveKind::TarGz),
+    (".tar.xz", ArchiveKind::TarXz),
+    (".txz", ArchiveKind::TarXz),
+    (".tar.zst", ArchiveKind::TarZst),
+    (".tzst", ArchiveKind::TarZst),
+];
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        SUFFIXES
+            .into_iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(_, kind)| kind)
+    }
+
+    /// The extension that identifies the archive, ignoring case
+    pub fn suffix(name: &str) -> Option<&'static str> {
+        let name = name.to_lowercase();
+        SUFFIXES
+            .into_iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(suffix, _)| suffix)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Relative to the root of the archive
+    pub path: PathBuf,
+    pub filetype: FileType,
+    pub size: u64,
+    pub modified: i64,
+}
+
+impl ArchiveEntry {
+    /// Many archives leave out the entries of folders that have files in them
+    fn implicit_folder(path: PathBuf) -> Self {
+        ArchiveEntry {
+            path,
+            filetype: FileType::Folder,
+            size: 0,
+            modified: 0,
+        }
+    }
+
+    pub fn to_file_data(&self, archive: &Path) -> FileData {
+        let path = archive.join(&self.path);
+        let mime = match self.filetype {
+            FileType::Folder => mime::DIRECTORY,
+            FileType::Link => mime::SYMLINK,
+            FileType::File => mime::from_extension(&path).unwrap_or(mime::UNKNOWN),
+        };
+        FileData {
+            name: self
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path,
+            filetype: self.filetype,
+            mime: Some(mime.to_owned()),
+            size: Some(self.size),
+            modified: Some(self.modified),
+        }
+    }
+}
+
+/// Drops `.` components. Absolute paths and paths with `..` in them are
+/// rejected, so that nothing can be listed or extracted outside the archive.
+pub fn clean_path(path: &Path) -> Option<PathBuf> {
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => cleaned.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    match cleaned.as_os_str().is_empty() {
+        true => None,
+        false => Some(cleaned),
+    }
+}
+
+/// Joins `relative` to `destination`, refusing paths that would be written
+/// through a symlink, for example one extracted earlier from the same archive
+pub fn safe_target(destination: &Path, relative: &Path) -> Option<PathBuf> {
+    let relative = clean_path(relative)?;
+    let mut target = destination.to_path_buf();
+    for component in relative.components() {
+        target.push(component);
+        if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            return None;
+        }
+    }
+    Some(target)
+}
+
+/// Days since the unix epoch for a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Zip files store the local time without a timezone, it is read as UTC
+fn zip_time(time: zip::DateTime) -> i64 {
+    days_from_civil(time.year().into(), time.month().into(), time.day().into()) * 86400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second())
+}
+
+fn is_zip_symlink(mode: Option<u32>) -> bool {
+    mode.is_some_and(|mode| mode & 0o170000 == 0o120000)
+}
+
+fn open_tar(
+    reader: impl Read + 'static,
+    kind: ArchiveKind,
+) -> io::Result<tar::Archive<Box<dyn Read>>> {
+    let reader = BufReader::new(reader);
+    let reader: Box<dyn Read> = match kind {
+        ArchiveKind::TarGz => Box::new(GzDecoder::new(reader)),
+        ArchiveKind::TarXz => Box::new(XzDecoder::new(reader)),
+        ArchiveKind::TarZst => Box::new(zstd::Decoder::with_buffer(reader)?),
+        ArchiveKind::Tar | ArchiveKind::Zip => Box::new(reader),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn open_zip(archive: &Path) -> io::Result<ZipArchive<BufReader<File>>> {
+    Ok(ZipArchive::new(BufReader::new(File::open(archive)?))?)
+}
+
+fn list_tar(archive: &Path, kind: ArchiveKind) -> io::Result<Vec<ArchiveEntry>> {
+    let mut entries = vec![];
+    for entry in open_tar(File::open(archive)?, kind)?.entries()? {
+        let entry = entry?;
+        let Some(path) = clean_path(&entry.path()?) else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        entries.push(ArchiveEntry {
+            path,
+            filetype: if entry_type.is_dir() {
+                FileType::Folder
+            } else if entry_type.is_symlink() {
+                FileType::Link
+            } else {
+                FileType::File
+            },
+            size: entry.size(),
+            modified: entry.header().mtime().unwrap_or(0) as i64,
+        });
+    }
+    Ok(entries)
+}
+
+fn list_zip(archive: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let mut zip = open_zip(archive)?;
+    let mut entries = vec![];
+    for index in 0..zip.len() {
+        // Raw access skips decompressing the contents
+        let file = zip.by_index_raw(index)?;
+        let Some(path) = file.enclosed_name().and_then(clean_path) else {
+            continue;
+        };
+        entries.push(ArchiveEntry {
+            path,
+            filetype: if file.is_dir() {
+                FileType::Folder
+            } else if is_zip_symlink(file.unix_mode()) {
+                FileType::Link
+            } else {
+                FileType::File
+            },
+            size: file.size(),
+            modified: zip_time(file.last_modified()),
+        });
+    }
+    Ok(entries)
+}
+
+/// Every entry of an archive. Tar files have no central directory, so they
+/// are read through once when the index is built.
+#[derive(Debug)]
+pub struct ArchiveIndex {
+    archive: PathBuf,
+    /// Modification time and size of the archive when it was read
+    stamp: (i64, u64),
+    entries: Vec<ArchiveEntry>,
+}
+
+fn stamp(archive: &Path) -> io::Result<(i64, u64)> {
+    let metadata = fs::metadata(archive)?;
+    Ok((platform::modified(&metadata), metadata.len()))
+}
+
+impl ArchiveIndex {
+    pub fn read(archive: &Path) -> Result<Self, CurrentDirError> {
+        let error = |err: io::Error| CurrentDirError::CannotReadArchive {
+            reason: err.to_string(),
+        };
+        let kind = ArchiveKind::from_path(archive).ok_or(CurrentDirError::CannotReadArchive {
+            reason: format!("{} isn't a supported archive", archive.display()),
+        })?;
+        let stamp = stamp(archive).map_err(error)?;
+        let entries = match kind {
+            ArchiveKind::Zip => list_zip(archive),
+            _ => list_tar(archive, kind),
+        }
+        .map_err(error)?;
+
+        Ok(ArchiveIndex {
+            archive: archive.to_owned(),
+            stamp,
+            entries,
+        })
+    }
+
+    pub fn is_dir(&self, inner: &Path) -> bool {
+        inner.as_os_str().is_empty()
+            || self.entries.iter().any(|entry| {
+                (entry.path == inner && entry.filetype == FileType::Folder)
+                    || (entry.path != inner && entry.path.starts_with(inner))
+            })
+    }
+
+    /// The entries directly inside `inner`, including folders that only
+    /// exist as a part of the paths of other entries
+    pub fn children(&self, inner: &Path) -> Vec<ArchiveEntry> {
+        let mut children = BTreeMap::new();
+        for entry in self.entries.iter() {
+            let Ok(relative) = entry.path.strip_prefix(inner) else {
+                continue;
+            };
+            let mut components = relative.components();
+            let Some(first) = components.next() else {
+                continue;
+            };
+            let path = inner.join(first);
+            if components.next().is_none() {
+                children.insert(path, entry.clone());
+            } else {
+                children
+                    .entry(path.clone())
+                    .or_insert_with(|| ArchiveEntry::implicit_folder(path));
+            }
+        }
+        children.into_values().collect()
+    }
+}
+
+/// Remembers the index of the last opened archive, so that moving around
+/// inside it doesn't read the whole archive again
+#[derive(Debug, Default)]
+pub struct ArchiveCache {
+    index: Mutex<Option<Arc<ArchiveIndex>>>,
+}
+
+impl ArchiveCache {
+    pub fn get(&self, archive: &Path) -> Result<Arc<ArchiveIndex>, CurrentDirError> {
+        let mut cached = self.index.lock().unwrap();
+        if let Some(index) = cached.as_ref() {
+            if index.archive == archive && stamp(archive).is_ok_and(|stamp| stamp == index.stamp) {
+                return Ok(Arc::clone(index));
+            }
+        }
+        let index = Arc::new(ArchiveIndex::read(archive)?);
+        *cached = Some(Arc::clone(&index));
+        Ok(index)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Extracted {
+    pub path: PathBuf,
+    /// Entries that would have been written outside the destination
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Whether a symlink at `relative` inside the destination points to
+/// somewhere inside the destination. The target doesn't have to exist, but
+/// `..` may only step out of folders that already exist in the destination.
+/// Going up from a link, or from a name that a later entry could make a
+/// link, would leave from wherever that link points to.
+pub fn link_stays_inside(destination: &Path, relative: &Path, link: &Path) -> bool {
+    let mut position = relative.parent().unwrap_or(Path::new("")).to_owned();
+    for component in link.components() {
+        match component {
+            Component::Normal(name) => position.push(name),
+            Component::CurDir => {}
+            Component::ParentDir
+                if position.parent().is_some()
+                    && fs::symlink_metadata(destination.join(&position))
+                        .is_ok_and(|metadata| metadata.is_dir()) =>
+            {
+                position.pop();
+            }
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// Extracts the entries at or under `inner` so that `inner` itself ends up
+/// directly inside the destination
+struct Extraction<'a> {
+    destination: &'a Path,
+    inner: &'a Path,
+    skipped: Vec<PathBuf>,
+}
+
+impl Extraction<'_> {
+    /// `None` for entries outside `inner` and for the skipped ones
+    fn target(&mut self, path: &Path) -> Option<(PathBuf, PathBuf)> {
+        let Some(cleaned) = clean_path(path) else {
+            self.skipped.push(path.to_owned());
+            return None;
+        };
+        if !cleaned.starts_with(self.inner) {
+            return None;
+        }
+        let base = self.inner.parent().unwrap_or(Path::new(""));
+        let relative = cleaned.strip_prefix(base).ok()?.to_owned();
+        match safe_target(self.destination, &relative) {
+            Some(target) => Some((relative, target)),
+            None => {
+                self.skipped.push(path.to_owned());
+                None
+            }
+        }
+    }
+
+    fn create_symlink(&mut self, relative: &Path, target: &Path, link: &Path) -> io::Result<()> {
+        let created = match link_stays_inside(self.destination, relative, link) {
+            true => symlink(link, target),
+            false => Err(io::Error::from(io::ErrorKind::Unsupported)),
+        };
+        match created {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                self.skipped.push(relative.to_owned());
+                Ok(())
+            }
+            created => created,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn symlink(link: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link, target)
+}
+
+/// Elsewhere a link has to be created as either a file or a folder link,
+/// which isn't known before its target is extracted
+#[cfg(not(unix))]
+fn symlink(_link: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+fn write_file(target: &Path, contents: &mut impl Read, mode: Option<u32>) -> io::Result<()> {
+    let mut file = File::create(target)?;
+    io::copy(contents, &mut file)?;
+    if let Some(mode) = mode {
+        set_mode(&file, mode)?;
+    }
+    Ok(())
+}
+
+/// Progress is the number of compressed bytes read from the archive
+fn extract_tar(
+    archive: &Path,
+    kind: ArchiveKind,
+    extraction: &mut Extraction,
+    job: &Job,
+    on_progress: &mut dyn FnMut(JobProgress),
+) -> io::Result<()> {
+    let total = fs::metadata(archive)?.len();
+    let read = Arc::new(AtomicU64::new(0));
+    let file = JobReader::new(File::open(archive)?, job, &read);
+
+    for entry in open_tar(file, kind)?.entries()? {
+        let mut entry = entry?;
+        let Some((relative, target)) = extraction.target(&entry.path()?) else {
+            continue;
+        };
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if entry_type.is_symlink() {
+            if let Some(link) = entry.link_name()? {
+                extraction.create_symlink(&relative, &target, &link)?;
+            }
+        } else if entry_type.is_hard_link() {
+            // Hard links point to an entry extracted earlier
+            let original = entry
+                .link_name()?
+                .and_then(|link| extraction.target(&link))
+                .filter(|(_, original)| original.is_file());
+            match original {
+                Some((_, original)) => fs::hard_link(original, &target)?,
+                None => extraction.skipped.push(relative),
+            }
+        } else if entry_type.is_file() {
+            let mode = entry.header().mode().ok();
+            write_file(&target, &mut entry, mode)?;
+        }
+        on_progress(job.progress(read.load(Ordering::SeqCst), total, Some(&target)));
+    }
+    Ok(())
+}
+
+/// Progress is the number of uncompressed bytes written
+fn extract_zip(
+    archive: &Path,
+    extraction: &mut Extraction,
+    job: &Job,
+    on_progress: &mut dyn FnMut(JobProgress),
+) -> io::Result<()> {
+    let mut zip = open_zip(archive)?;
+    let mut total = 0;
+    for index in 0..zip.len() {
+        total += zip.by_index_raw(index)?.size();
+    }
+    let read = Arc::new(AtomicU64::new(0));
+
+    for index in 0..zip.len() {
+        let file = zip.by_index(index)?;
+        let name = PathBuf::from(file.name());
+        let Some((relative, target)) = extraction.target(&name) else {
+            continue;
+        };
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let is_dir = file.is_dir();
+        let mode = file.unix_mode();
+        let mut contents = JobReader::new(file, job, &read);
+        if is_dir {
+            fs::create_dir_all(&target)?;
+        } else if is_zip_symlink(mode) {
+            let mut link = String::new();
+            contents.read_to_string(&mut link)?;
+            extraction.create_symlink(&relative, &target, Path::new(&link))?;
+        } else {
+            write_file(&target, &mut contents, mode)?;
+        }
+        on_progress(job.progress(read.load(Ordering::SeqCst), total, Some(&target)));
+    }
+    Ok(())
+}
+
+fn extract_into(
+    archive: &Path,
+    inner: &Path,
+    destination: &Path,
+    job: &Job,
+    on_progress: &mut dyn FnMut(JobProgress),
+) -> Result<Extracted, CurrentDirError> {
+    let kind = ArchiveKind::from_path(archive).ok_or(CurrentDirError::CannotReadArchive {
+        reason: format!("{} isn't a supported archive", archive.display()),
+    })?;
+    let mut extraction = Extraction {
+        destination,
+        inner,
+        skipped: vec![],
+    };
+    match kind {
+        ArchiveKind::Zip => extract_zip(archive, &mut extraction, job, on_progress),
+        _ => extract_tar(archive, kind, &mut extraction, job, on_progress),
+    }
+    .map_err(|err| job.io_error(err, |reason| CurrentDirError::CannotExtract { reason }))?;
+
+    Ok(Extracted {
+        path: match inner.file_name() {
+            Some(name) => destination.join(name),
+            None => destination.to_owned(),
+        },
+        skipped: extraction.skipped,
+    })
+}
+
+/// Copies the entry at `inner` out of the archive into `destination`,
+/// together with everything inside it. An empty `inner` extracts the whole
+/// archive.
+pub fn extract(
+    archive: &Path,
+    inner: &Path,
+    destination: &Path,
+) -> Result<Extracted, CurrentDirError> {
+    extract_into(archive, inner, destination, &Job::detached(), &mut |_| {})
+}
+
+/// A folder next to the archive, named after it without the extension
+pub fn free_folder_name(archive: &Path) -> PathBuf {
+    let name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = ArchiveKind::suffix(&name)
+        .and_then(|suffix| name.get(..name.len().saturating_sub(suffix.len())))
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or("archive");
+    let folder = archive.parent().unwrap_or(Path::new("/"));
+
+    let mut candidate = folder.join(stem);
+    let mut number = 2;
+    while fs::symlink_metadata(&candidate).is_ok() {
+        candidate = folder.join(format!("{} ({})", stem, number));
+        number += 1;
+    }
+    candidate
+}
+
+/// Extracts the whole archive as a cancellable job. Without a destination a
+/// new folder is created next to the archive, and removed again if the
+/// extraction fails.
+pub fn extract_archive(
+    archive: &Path,
+    destination: Option<&Path>,
+    job: &Job,
+    mut on_progress: impl FnMut(JobProgress),
+) -> Result<Extracted, CurrentDirError> {
+    let (destination, created) = match destination {
+        Some(destination) => (destination.to_owned(), false),
+        None => (free_folder_name(archive), true),
+    };
+    fs::create_dir_all(&destination).map_err(|err| CurrentDirError::CannotExtract {
+        reason: err.to_string(),
+    })?;
+
+    let extracted = extract_into(archive, Path::new(""), &destination, job, &mut on_progress);
+    if extracted.is_err() && created {
+        let _ = fs::remove_dir_all(&destination);
+    }
+    extracted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        clean_path, days_from_civil, extract, extract_archive, free_folder_name, safe_target,
+        ArchiveIndex, ArchiveKind,
+    };
+    use crate::{
+        filecache::FileCache, jobs::Job, listing::ListingOptions, sorting::SortMode, CurrentDir,
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use std::{
+        fs::{self, File},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn create_tar_gz(path: &Path) {
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents) in [("docs/readme.txt", "hello"), ("top.txt", "top")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn create_zip(path: &Path) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        zip.add_directory("photos/", FileOptions::default())
+            .unwrap();
+        zip.start_file("photos/2023/cat.jpg", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"meow").unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escaping_entries_are_skipped() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+
+        let mut builder = tar::Builder::new(File::create(root.join("evil.tar")).unwrap());
+        let entries: [(&str, tar::EntryType, Option<&str>); 9] = [
+            ("../evil.txt", tar::EntryType::Regular, None),
+            ("escape", tar::EntryType::Symlink, Some("../../outside")),
+            ("absolute", tar::EntryType::Symlink, Some("/etc")),
+            ("loop", tar::EntryType::Symlink, Some(".")),
+            ("loop/x.txt", tar::EntryType::Regular, None),
+            // Only `..` from the link itself leads out
+            ("through", tar::EntryType::Symlink, Some("loop/..")),
+            // The name could still become a link after this one
+            ("later", tar::EntryType::Symlink, Some("missing/..")),
+            ("sub", tar::EntryType::Directory, None),
+            ("inside", tar::EntryType::Symlink, Some("sub/../loop")),
+        ];
+        for (name, entry_type, link) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_mode(0o644);
+            header.set_size(0);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, io::empty()).unwrap();
+        }
+        builder.finish().unwrap();
+        drop(builder);
+
+        let extracted = extract_archive(
+            &root.join("evil.tar"),
+            Some(&root.join("out")),
+            &Job::detached(),
+            |_| {},
+        )
+        .unwrap();
+        let escaped = root.join("evil.txt").exists();
+        let inside = fs::read_link(root.join("out/inside")).is_ok();
+
+        assert_eq!(
+            extracted.skipped,
+            vec![
+                PathBuf::from("../evil.txt"),
+                PathBuf::from("escape"),
+                PathBuf::from("absolute"),
+                PathBuf::from("loop/x.txt"),
+                PathBuf::from("through"),
+                PathBuf::from("later"),
+            ]
+        );
+        assert!(!escaped);
+        assert!(inside);
+        assert_eq!(
+            free_folder_name(Path::new("/a/b.TAR.GZ")),
+            Path::new("/a/b")
+        );
+    }
+
+    #[test]
+    fn archive_kinds_are_detected() {
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("a.TAR.GZ")),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("a.tar.zst")),
+            Some(ArchiveKind::TarZst)
+        );
+        assert_eq!(ArchiveKind::from_path(Path::new("a.gz")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unsafe_paths_are_rejected() {
+        assert_eq!(clean_path(Path::new("./a/./b")), Some(PathBuf::from("a/b")));
+        assert_eq!(clean_path(Path::new("a/../../etc/passwd")), None);
+        assert_eq!(clean_path(Path::new("/etc/passwd")), None);
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        std::os::unix::fs::symlink("/tmp", root.join("link")).unwrap();
+        let through_link = safe_target(&root, Path::new("link/file"));
+        let normal = safe_target(&root, Path::new("folder/file"));
+
+        assert_eq!(through_link, None);
+        assert_eq!(normal, Some(root.join("folder/file")));
+    }
+
+    #[test]
+    fn implicit_folders_are_listed() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        create_zip(&root.join("a.zip"));
+
+        let index = ArchiveIndex::read(&root.join("a.zip")).unwrap();
+
+        let children = index.children(Path::new("photos"));
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].path, Path::new("photos/2023"));
+        assert!(index.is_dir(Path::new("photos/2023")));
+        assert!(!index.is_dir(Path::new("photos/2023/cat.jpg")));
+        assert_eq!(index.children(Path::new("photos/2023"))[0].size, 4);
+    }
+
+    #[tokio::test]
+    async fn archives_are_browsed_like_folders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("out")).unwrap();
+        create_tar_gz(&root.join("backup.tar.gz"));
+
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let mut current_dir = CurrentDir::new(&root, cache).unwrap();
+        current_dir
+            .move_to_dir(&root.join("backup.tar.gz"), false)
+            .unwrap();
+        let names = current_dir
+            .get_siblings(&SortMode::default(), &ListingOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect::<Vec<String>>();
+        current_dir
+            .move_to_dir(&root.join("backup.tar.gz/docs"), false)
+            .unwrap();
+        let not_a_folder = current_dir.move_to_dir(&root.join("backup.tar.gz/top.txt"), false);
+        let extracted = current_dir
+            .extract_entries(vec![String::from("readme.txt")], &root.join("out"))
+            .unwrap();
+        let whole = extract(
+            &root.join("backup.tar.gz"),
+            Path::new(""),
+            &root.join("all"),
+        );
+        let contents = fs::read_to_string(&extracted[0]).unwrap();
+        let top = fs::read_to_string(root.join("all/top.txt")).unwrap();
+
+        assert_eq!(names, vec!["docs", "top.txt"]);
+        assert_eq!(current_dir.get_path(), root.join("backup.tar.gz/docs"));
+        assert!(not_a_folder.is_err());
+        assert_eq!(extracted, vec![root.join("out/readme.txt")]);
+        assert_eq!(contents, "hello");
+        assert_eq!(whole.unwrap().path, root.join("all"));
+        assert_eq!(top, "top");
+    }
+}
@@ -0,0 +1,394 @@
+use super::{database::AppDatabase, uri, CurrentDirError};
+use rusqlite::{named_params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub id: i64,
+    pub name: String,
+    pub path: PathBuf,
+    pub icon: Option<String>,
+    pub group: Option<String>,
+    /// Order of the bookmark inside its group
+    pub position: i64,
+    /// Set when listing, bookmarks to deleted locations are kept but flagged
+    pub exists: bool,
+}
+
+impl Bookmark {
+    fn from_row(row: &rusqlite::Row) -> Result<Self, rusqlite::Error> {
+        let path = PathBuf::from(row.get::<_, String>(2)?);
+        Ok(Bookmark {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            exists: path.exists(),
+            path,
+            icon: row.get(3)?,
+            group: row.get(4)?,
+            position: row.get(5)?,
+        })
+    }
+}
+
+/// Location of the bookmarks GTK file choosers and Nautilus use
+pub fn gtk_bookmarks_file() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"))
+        .join("gtk-3.0")
+        .join("bookmarks")
+}
+
+/// Every line is an URI, optionally followed by a label
+pub fn parse_gtk_bookmarks(content: &str) -> Vec<(String, PathBuf)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (uri, label) = line.split_once(' ').unwrap_or((line, ""));
+            let path = uri::path_from_file_uri(uri.trim())?;
+            let name = match label.trim() {
+                "" => path.file_name()?.to_string_lossy().to_string(),
+                label => label.to_owned(),
+            };
+            Some((name, path))
+        })
+        .collect()
+}
+
+/// The group and the position of a bookmark
+fn locate(
+    transaction: &rusqlite::Transaction,
+    id: i64,
+) -> Result<Option<(Option<String>, i64)>, rusqlite::Error> {
+    transaction
+        .query_row(
+            "SELECT group_name, position FROM bookmarks WHERE id = ?1",
+            [id],
+            |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?)),
+        )
+        .optional()
+}
+
+/// Moves the bookmarks after `position` back by one, after the bookmark at
+/// it left the group
+fn close_gap(
+    transaction: &rusqlite::Transaction,
+    group: &Option<String>,
+    position: i64,
+) -> Result<(), rusqlite::Error> {
+    transaction.execute(
+        "UPDATE bookmarks SET position = position - 1
+         WHERE group_name IS ?1 AND position > ?2",
+        (group, position),
+    )?;
+    Ok(())
+}
+
+impl AppDatabase {
+    pub async fn list_bookmarks(&self) -> Result<Vec<Bookmark>, CurrentDirError> {
+        Ok(self
+            .connection
+            .call(|conn| {
+                let mut statement = conn.prepare(
+                    "SELECT id, name, path, icon, group_name, position FROM bookmarks
+                     ORDER BY group_name, position",
+                )?;
+                let bookmarks = statement
+                    .query_map([], Bookmark::from_row)?
+                    .collect::<Result<Vec<Bookmark>, rusqlite::Error>>()?;
+                Ok(bookmarks)
+            })
+            .await?)
+    }
+
+    /// New bookmarks are placed last in their group
+    pub async fn add_bookmark(
+        &self,
+        name: String,
+        path: PathBuf,
+        icon: Option<String>,
+        group: Option<String>,
+    ) -> Result<Bookmark, CurrentDirError> {
+        Ok(self
+            .connection
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO bookmarks (name, path, icon, group_name, position)
+                     SELECT :name, :path, :icon, :group,
+                        COALESCE(MAX(position) + 1, 0) FROM bookmarks WHERE group_name IS :group",
+                    named_params! {
+                        ":name": name,
+                        ":path": path.to_string_lossy(),
+                        ":icon": icon,
+                        ":group": group,
+                    },
+                )?;
+                conn.query_row(
+                    "SELECT id, name, path, icon, group_name, position FROM bookmarks
+                     WHERE id = ?1",
+                    [conn.last_insert_rowid()],
+                    Bookmark::from_row,
+                )
+            })
+            .await?)
+    }
+
+    /// A bookmark moved to another group goes to the end of it
+    pub async fn update_bookmark(
+        &self,
+        id: i64,
+        name: String,
+        icon: Option<String>,
+        group: Option<String>,
+    ) -> Result<(), CurrentDirError> {
+        let updated = self
+            .connection
+            .call(move |conn| {
+                let transaction = conn.transaction()?;
+                let Some((old_group, old_position)) = locate(&transaction, id)? else {
+                    return Ok(false);
+                };
+
+                if old_group != group {
+                    close_gap(&transaction, &old_group, old_position)?;
+                    transaction.execute(
+                        "UPDATE bookmarks SET position = (
+                             SELECT COALESCE(MAX(position) + 1, 0) FROM bookmarks
+                             WHERE group_name IS ?2
+                         ) WHERE id = ?1",
+                        (id, &group),
+                    )?;
+                }
+                transaction.execute(
+                    "UPDATE bookmarks SET name = ?2, icon = ?3, group_name = ?4 WHERE id = ?1",
+                    (id, name, icon, group),
+                )?;
+                transaction.commit()?;
+                Ok(true)
+            })
+            .await?;
+        match updated {
+            true => Ok(()),
+            false => Err(CurrentDirError::BookmarkNotFound { id }),
+        }
+    }
+
+    pub async fn remove_bookmark(&self, id: i64) -> Result<(), CurrentDirError> {
+        let removed = self
+            .connection
+            .call(move |conn| {
+                let transaction = conn.transaction()?;
+                let Some((group, position)) = locate(&transaction, id)? else {
+                    return Ok(false);
+                };
+
+                transaction.execute("DELETE FROM bookmarks WHERE id = ?1", [id])?;
+                close_gap(&transaction, &group, position)?;
+                transaction.commit()?;
+                Ok(true)
+            })
+            .await?;
+        match removed {
+            true => Ok(()),
+            false => Err(CurrentDirError::BookmarkNotFound { id }),
+        }
+    }
+
+    /// Moves the bookmark to `position` inside its group, shifting the ones
+    /// between the old and the new position. Positions past the ends of the
+    /// group are clamped.
+    pub async fn move_bookmark(&self, id: i64, position: i64) -> Result<(), CurrentDirError> {
+        let moved = self
+            .connection
+            .call(move |conn| {
+                let transaction = conn.transaction()?;
+                let Some((group, old_position)) = locate(&transaction, id)? else {
+                    return Ok(false);
+                };
+                let last_position = transaction.query_row(
+                    "SELECT MAX(position) FROM bookmarks WHERE group_name IS ?1",
+                    [&group],
+                    |row| row.get::<_, i64>(0),
+                )?;
+                let position = position.clamp(0, last_position);
+
+                if position > old_position {
+                    transaction.execute(
+                        "UPDATE bookmarks SET position = position - 1
+                         WHERE group_name IS ?1 AND position > ?2 AND position <= ?3",
+                        (&group, old_position, position),
+                    )?;
+                } else {
+                    transaction.execute(
+                        "UPDATE bookmarks SET position = position + 1
+                         WHERE group_name IS ?1 AND position >= ?3 AND position < ?2",
+                        (&group, old_position, position),
+                    )?;
+                }
+                transaction.execute(
+                    "UPDATE bookmarks SET position = ?2 WHERE id = ?1",
+                    (id, position),
+                )?;
+                transaction.commit()?;
+                Ok(true)
+            })
+            .await?;
+        match moved {
+            true => Ok(()),
+            false => Err(CurrentDirError::BookmarkNotFound { id }),
+        }
+    }
+
+    /// Returns the number of imported bookmarks. Paths that are already
+    /// bookmarked are skipped.
+    pub async fn import_gtk_bookmarks(&self, file: &Path) -> Result<usize, CurrentDirError> {
+        let content = fs::read_to_string(file).map_err(|err| CurrentDirError::CannotReadFile {
+            reason: err.to_string(),
+        })?;
+        let existing = self
+            .list_bookmarks()
+            .await?
+            .into_iter()
+            .map(|bookmark| bookmark.path)
+            .collect::<Vec<PathBuf>>();
+
+        let mut imported = 0;
+        for (name, path) in parse_gtk_bookmarks(&content) {
+            if existing.contains(&path) {
+                continue;
+            }
+            self.add_bookmark(name, path, None, None).await?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_gtk_bookmarks;
+    use crate::database::AppDatabase;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn gtk_bookmarks_are_parsed() {
+        let content = "file:///home/vili/Downloads
+file:///home/vili/My%20Projects Projects
+sftp://server/home/vili
+
+file:///tmp Temporary files";
+
+        assert_eq!(
+            parse_gtk_bookmarks(content),
+            vec![
+                (
+                    String::from("Downloads"),
+                    PathBuf::from("/home/vili/Downloads")
+                ),
+                (
+                    String::from("Projects"),
+                    PathBuf::from("/home/vili/My Projects")
+                ),
+                (String::from("Temporary files"), PathBuf::from("/tmp")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn bookmarks_are_ordered_inside_groups() {
+        let database = AppDatabase::open_in_memory().await.unwrap();
+        let mut ids = vec![];
+        for name in ["a", "b", "c"] {
+            let bookmark = database
+                .add_bookmark(name.to_owned(), PathBuf::from("/"), None, None)
+                .await
+                .unwrap();
+            ids.push(bookmark.id);
+        }
+        let other = database
+            .add_bookmark(
+                String::from("other"),
+                PathBuf::from("/"),
+                None,
+                Some(String::from("work")),
+            )
+            .await
+            .unwrap();
+
+        database.move_bookmark(ids[2], 0).await.unwrap();
+        let names = || async {
+            database
+                .list_bookmarks()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|bookmark| (bookmark.name, bookmark.position))
+                .collect::<Vec<(String, i64)>>()
+        };
+        let moved = names().await;
+        database.move_bookmark(ids[2], 10).await.unwrap();
+        let clamped = names().await;
+        database
+            .update_bookmark(ids[0], String::from("a"), None, Some(String::from("work")))
+            .await
+            .unwrap();
+        database.remove_bookmark(other.id).await.unwrap();
+        let regrouped = names().await;
+
+        let positioned = |names: &[(&str, i64)]| {
+            names
+                .iter()
+                .map(|(name, position)| (name.to_string(), *position))
+                .collect::<Vec<(String, i64)>>()
+        };
+        assert_eq!(other.position, 0);
+        assert_eq!(
+            moved,
+            positioned(&[("c", 0), ("a", 1), ("b", 2), ("other", 0)])
+        );
+        assert_eq!(
+            clamped,
+            positioned(&[("a", 0), ("b", 1), ("c", 2), ("other", 0)])
+        );
+        assert_eq!(regrouped, positioned(&[("b", 0), ("c", 1), ("a", 0)]));
+    }
+
+    #[tokio::test]
+    async fn missing_bookmarks_are_flagged() {
+        let database = AppDatabase::open_in_memory().await.unwrap();
+        database
+            .add_bookmark(
+                String::from("gone"),
+                PathBuf::from("/does/not/exist"),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        database
+            .add_bookmark(String::from("root"), PathBuf::from("/"), None, None)
+            .await
+            .unwrap();
+
+        let bookmarks = database.list_bookmarks().await.unwrap();
+        assert!(!bookmarks[0].exists);
+        assert!(bookmarks[1].exists);
+        assert_eq!(bookmarks[1].path, Path::new("/"));
+    }
+
+    #[tokio::test]
+    async fn unknown_bookmark_is_not_found() {
+        let database = AppDatabase::open_in_memory().await.unwrap();
+
+        assert!(database.remove_bookmark(42).await.is_err());
+        assert!(database.move_bookmark(42, 0).await.is_err());
+        assert!(database
+            .update_bookmark(42, String::new(), None, None)
+            .await
+            .is_err());
+    }
+}
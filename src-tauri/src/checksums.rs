@@ -0,0 +1,358 @@
+use super::{
+    hashing::{hash_file, HashAlgorithm},
+    jobs::{Job, JobId, JobProgress},
+    CurrentDirError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use walkdir::WalkDir;
+
+impl HashAlgorithm {
+    /// Named after the tool that checks the manifest, like `sha256sum -c`
+    pub fn manifest_extension(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5sum",
+            HashAlgorithm::Sha1 => "sha1sum",
+            HashAlgorithm::Sha256 => "sha256sum",
+            HashAlgorithm::Blake3 => "b3sum",
+        }
+    }
+
+    fn of_name(name: &str) -> Option<Self> {
+        match name {
+            "md5" => Some(HashAlgorithm::Md5),
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "b3" | "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Works for names such as `release.sha256sum`, `release.md5` and
+    /// `SHA256SUMS`. Other manifests are recognized by the length of their
+    /// first hash.
+    fn of_manifest(manifest: &Path, first_hash: Option<&str>) -> Option<Self> {
+        let named = |part: Option<&std::ffi::OsStr>| {
+            let part = part?.to_string_lossy().to_lowercase();
+            let part = part
+                .strip_suffix("sums")
+                .or_else(|| part.strip_suffix("sum"))
+                .unwrap_or(&part);
+            HashAlgorithm::of_name(part)
+        };
+        if let Some(algorithm) = named(manifest.extension()).or_else(|| named(manifest.file_stem()))
+        {
+            return Some(algorithm);
+        }
+        match first_hash?.len() {
+            32 => Some(HashAlgorithm::Md5),
+            40 => Some(HashAlgorithm::Sha1),
+            64 => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+fn total_size(paths: &[PathBuf]) -> u64 {
+    paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FileChecksums {
+    pub path: PathBuf,
+    pub hashes: BTreeMap<HashAlgorithm, String>,
+    /// Why the entry wasn't hashed, such as it being a folder or a FIFO
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Checksums {
+    pub job_id: JobId,
+    pub files: Vec<FileChecksums>,
+}
+
+/// Entries that aren't files are reported with an error instead of failing
+/// the whole job.
+pub fn compute(
+    paths: &[PathBuf],
+    algorithms: &[HashAlgorithm],
+    job: &Job,
+    mut on_progress: impl FnMut(JobProgress),
+) -> Result<Checksums, CurrentDirError> {
+    let total = total_size(paths);
+    let read = Arc::new(AtomicU64::new(0));
+    let mut files = vec![];
+    for path in paths {
+        let (hashes, error) = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => {
+                (hash_file(path, algorithms, u64::MAX, job, &read)?, None)
+            }
+            Ok(_) => (
+                BTreeMap::new(),
+                Some(format!("{} isn't a file", path.display())),
+            ),
+            Err(err) => (BTreeMap::new(), Some(err.to_string())),
+        };
+        files.push(FileChecksums {
+            path: path.clone(),
+            hashes,
+            error,
+        });
+        on_progress(job.progress(read.load(Ordering::SeqCst), total, Some(path)));
+    }
+    Ok(Checksums {
+        job_id: job.id,
+        files,
+    })
+}
+
+/// Lines look like `<hash>  <path>`, with `*` instead of the second space
+/// for files that were read in binary mode
+fn parse_manifest(contents: &str) -> Vec<(String, PathBuf)> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (hash, path) = line.split_once(' ')?;
+            let path = path.strip_prefix([' ', '*']).unwrap_or(path);
+            Some((hash.to_lowercase(), PathBuf::from(path)))
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Missing,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CheckedFile {
+    /// As written in the manifest
+    pub path: PathBuf,
+    pub expected: String,
+    /// `None` when the file is missing or couldn't be read
+    pub actual: Option<String>,
+    pub status: CheckStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ManifestCheck {
+    pub job_id: JobId,
+    pub manifest: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub files: Vec<CheckedFile>,
+    pub failed: usize,
+    pub missing: usize,
+}
+
+/// Paths in the manifest are relative to its folder. Files that can't be
+/// read and entries that aren't files count as failed.
+pub fn verify_manifest(
+    manifest: &Path,
+    job: &Job,
+    mut on_progress: impl FnMut(JobProgress),
+) -> Result<ManifestCheck, CurrentDirError> {
+    let contents = fs::read_to_string(manifest).map_err(|err| CurrentDirError::CannotReadFile {
+        reason: err.to_string(),
+    })?;
+    let entries = parse_manifest(&contents);
+    let algorithm =
+        HashAlgorithm::of_manifest(manifest, entries.first().map(|(hash, _)| hash.as_str()))
+            .ok_or(CurrentDirError::UnknownManifest {
+                path: manifest.to_string_lossy().to_string(),
+            })?;
+    let folder = manifest.parent().unwrap_or(Path::new(""));
+    let paths = entries
+        .iter()
+        .map(|(_, path)| folder.join(path))
+        .collect::<Vec<PathBuf>>();
+    let total = total_size(&paths);
+    let read = Arc::new(AtomicU64::new(0));
+
+    let mut files = vec![];
+    for ((expected, path), full_path) in entries.into_iter().zip(paths) {
+        let is_file = fs::metadata(&full_path).map(|metadata| metadata.is_file());
+        let hashed = match is_file {
+            Ok(true) => hash_file(&full_path, &[algorithm], u64::MAX, job, &read),
+            Ok(false) => Err(CurrentDirError::CannotReadFile {
+                reason: format!("{} isn't a file", full_path.display()),
+            }),
+            Err(err) => Err(CurrentDirError::CannotReadFile {
+                reason: err.to_string(),
+            }),
+        };
+        let (actual, status) = match hashed {
+            Ok(mut hashes) => {
+                let actual = hashes.remove(&algorithm);
+                let status = match actual.as_ref() == Some(&expected) {
+                    true => CheckStatus::Ok,
+                    false => CheckStatus::Failed,
+                };
+                (actual, status)
+            }
+            Err(CurrentDirError::Cancelled) => return Err(CurrentDirError::Cancelled),
+            Err(_) if !full_path.exists() => (None, CheckStatus::Missing),
+            Err(_) => (None, CheckStatus::Failed),
+        };
+        on_progress(job.progress(read.load(Ordering::SeqCst), total, Some(&full_path)));
+        files.push(CheckedFile {
+            path,
+            expected,
+            actual,
+            status,
+        });
+    }
+
+    let count = |status| files.iter().filter(|file| file.status == status).count();
+    Ok(ManifestCheck {
+        job_id: job.id,
+        manifest: manifest.to_owned(),
+        algorithm,
+        failed: count(CheckStatus::Failed),
+        missing: count(CheckStatus::Missing),
+        files,
+    })
+}
+
+/// Writes `<folder name>.<sha256sum or similar>` into the folder with every
+/// file under it, so that it can be checked with `sha256sum -c` from there.
+/// Returns the path of the manifest.
+pub fn generate_manifest(
+    folder: &Path,
+    algorithm: HashAlgorithm,
+    job: &Job,
+    mut on_progress: impl FnMut(JobProgress),
+) -> Result<PathBuf, CurrentDirError> {
+    let name = folder
+        .file_name()
+        .map_or("checksums".into(), |name| name.to_string_lossy());
+    let manifest = folder.join(format!("{}.{}", name, algorithm.manifest_extension()));
+    let paths = WalkDir::new(folder)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() && entry.path() != manifest)
+        .map(|entry| entry.into_path())
+        .collect::<Vec<PathBuf>>();
+    let total = total_size(&paths);
+    let read = Arc::new(AtomicU64::new(0));
+
+    let mut contents = String::new();
+    for path in &paths {
+        let mut hashes = hash_file(path, &[algorithm], u64::MAX, job, &read)?;
+        let relative = path.strip_prefix(folder).unwrap_or(path);
+        contents.push_str(&format!(
+            "{}  {}\n",
+            hashes.remove(&algorithm).unwrap_or_default(),
+            relative.display()
+        ));
+        on_progress(job.progress(read.load(Ordering::SeqCst), total, Some(path)));
+    }
+    fs::write(&manifest, contents).map_err(|err| CurrentDirError::CannotCreateFile {
+        reason: err.to_string(),
+    })?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute, generate_manifest, verify_manifest, CheckStatus};
+    use crate::{hashing::HashAlgorithm, jobs::Job};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn manifest_algorithm_comes_from_the_name_first() {
+        let of = |name: &str, hash: Option<&str>| HashAlgorithm::of_manifest(Path::new(name), hash);
+
+        assert_eq!(of("md5-tool.sha256sum", None), Some(HashAlgorithm::Sha256));
+        assert_eq!(of("SHA1SUMS", None), Some(HashAlgorithm::Sha1));
+        assert_eq!(of("release.b3", None), Some(HashAlgorithm::Blake3));
+        assert_eq!(
+            of("sha256-notes.txt", Some(&"0".repeat(32))),
+            Some(HashAlgorithm::Md5)
+        );
+        assert_eq!(of("checksums", Some("abc")), None);
+    }
+
+    #[test]
+    fn manifests_are_generated_and_verified() {
+        let temp = tempfile::tempdir().unwrap();
+        let folder = temp.path().join("release");
+        fs::create_dir_all(folder.join("bin")).unwrap();
+        fs::write(folder.join("notes.txt"), "abc").unwrap();
+        fs::write(folder.join("bin/tool"), "tool").unwrap();
+        fs::write(folder.join("gone.txt"), "gone").unwrap();
+
+        let checksums = compute(
+            &[folder.join("notes.txt"), folder.join("bin")],
+            &[
+                HashAlgorithm::Md5,
+                HashAlgorithm::Sha1,
+                HashAlgorithm::Sha256,
+                HashAlgorithm::Blake3,
+            ],
+            &Job::detached(),
+            |_| {},
+        )
+        .unwrap();
+        let manifest =
+            generate_manifest(&folder, HashAlgorithm::Sha256, &Job::detached(), |_| {}).unwrap();
+        let contents = fs::read_to_string(&manifest).unwrap();
+        fs::write(&manifest, format!("{}{}  bin\n", contents, "0".repeat(64))).unwrap();
+        fs::write(folder.join("bin/tool"), "changed").unwrap();
+        fs::remove_file(folder.join("gone.txt")).unwrap();
+        let check = verify_manifest(&manifest, &Job::detached(), |_| {}).unwrap();
+
+        let hashes = &checksums.files[0].hashes;
+        assert_eq!(
+            hashes[&HashAlgorithm::Md5],
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            hashes[&HashAlgorithm::Sha1],
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hashes[&HashAlgorithm::Sha256],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hashes[&HashAlgorithm::Blake3],
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(manifest, folder.join("release.sha256sum"));
+        assert!(contents.contains(&format!("{}  notes.txt\n", hashes[&HashAlgorithm::Sha256])));
+        assert_eq!(
+            check
+                .files
+                .iter()
+                .map(|file| (file.path.to_str().unwrap(), file.status))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bin/tool", CheckStatus::Failed),
+                ("gone.txt", CheckStatus::Missing),
+                ("notes.txt", CheckStatus::Ok),
+                ("bin", CheckStatus::Failed),
+            ]
+        );
+        assert_eq!((check.failed, check.missing), (2, 1));
+        assert!(checksums.files[1].hashes.is_empty());
+        assert!(checksums.files[1].error.is_some());
+    }
+}
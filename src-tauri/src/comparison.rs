@@ -0,0 +1,410 @@
+use super::{
+    hashing::{hash_file, HashAlgorithm},
+    jobs::{Job, JobId, JobProgress},
+    platform, CurrentDirError, FileType,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicU64, Arc},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompareMode {
+    /// Files with the same size and modification time count as identical
+    #[default]
+    SizeAndModified,
+    /// Files of the same size are read and hashed
+    Contents,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    OnlyLeft,
+    OnlyRight,
+    Identical,
+    Different,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ComparedSide {
+    pub filetype: FileType,
+    pub size: u64,
+    pub modified: i64,
+}
+
+impl From<&fs::Metadata> for ComparedSide {
+    fn from(metadata: &fs::Metadata) -> Self {
+        ComparedSide {
+            filetype: FileType::from(metadata.file_type()),
+            size: metadata.len(),
+            modified: platform::modified(metadata),
+        }
+    }
+}
+
+/// Folders on both sides are identical when everything inside them is
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ComparedEntry {
+    pub name: String,
+    /// Relative to both of the compared folders
+    pub path: PathBuf,
+    pub left: Option<ComparedSide>,
+    pub right: Option<ComparedSide>,
+    pub difference: Difference,
+    pub children: Vec<ComparedEntry>,
+}
+
+/// Counts every entry in the tree, folders included
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompareSummary {
+    pub only_left: usize,
+    pub only_right: usize,
+    pub identical: usize,
+    pub different: usize,
+}
+
+impl CompareSummary {
+    fn count(&mut self, entries: &[ComparedEntry]) {
+        for entry in entries {
+            match entry.difference {
+                Difference::OnlyLeft => self.only_left += 1,
+                Difference::OnlyRight => self.only_right += 1,
+                Difference::Identical => self.identical += 1,
+                Difference::Different => self.different += 1,
+            }
+            self.count(&entry.children);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub job_id: JobId,
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub mode: CompareMode,
+    pub summary: CompareSummary,
+    pub entries: Vec<ComparedEntry>,
+}
+
+/// Unreadable folders count as empty
+fn list(folder: &Path) -> BTreeMap<OsString, fs::Metadata> {
+    fs::read_dir(folder)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| Some((entry.file_name(), entry.metadata().ok()?)))
+        .collect()
+}
+
+struct Compare<'a, F> {
+    mode: CompareMode,
+    job: &'a Job,
+    read: Arc<AtomicU64>,
+    on_progress: F,
+}
+
+impl<F: FnMut(JobProgress)> Compare<'_, F> {
+    /// Progress is counted in top level entries
+    fn folders(
+        &mut self,
+        left: &Path,
+        right: &Path,
+        relative: &Path,
+        is_top_level: bool,
+    ) -> Result<Vec<ComparedEntry>, CurrentDirError> {
+        let left_entries = list(left);
+        let right_entries = list(right);
+        let names = left_entries
+            .keys()
+            .chain(right_entries.keys())
+            .collect::<BTreeSet<&OsString>>();
+
+        let mut entries = vec![];
+        for (index, name) in names.iter().enumerate() {
+            self.job.check()?;
+            let entry = self.entry(
+                &left.join(name),
+                left_entries.get(*name),
+                &right.join(name),
+                right_entries.get(*name),
+                &relative.join(name),
+            )?;
+            entries.push(entry);
+            if is_top_level {
+                let progress = self.job.progress(
+                    index as u64 + 1,
+                    names.len() as u64,
+                    Some(&relative.join(name)),
+                );
+                (self.on_progress)(progress);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// A file on one side and a folder on the other are different, with
+    /// the contents of the folder only on its side
+    fn entry(
+        &mut self,
+        left: &Path,
+        left_metadata: Option<&fs::Metadata>,
+        right: &Path,
+        right_metadata: Option<&fs::Metadata>,
+        relative: &Path,
+    ) -> Result<ComparedEntry, CurrentDirError> {
+        let (difference, children) = match (left_metadata, right_metadata) {
+            (Some(_), None) => (Difference::OnlyLeft, one_sided(left, relative, true)),
+            (None, Some(_)) => (Difference::OnlyRight, one_sided(right, relative, false)),
+            (Some(l), Some(r)) if l.is_dir() && r.is_dir() => {
+                let children = self.folders(left, right, relative, false)?;
+                let difference = match children
+                    .iter()
+                    .all(|child| child.difference == Difference::Identical)
+                {
+                    true => Difference::Identical,
+                    false => Difference::Different,
+                };
+                (difference, children)
+            }
+            (Some(l), Some(r)) if l.is_dir() || r.is_dir() => {
+                let children = match l.is_dir() {
+                    true => one_sided(left, relative, true),
+                    false => one_sided(right, relative, false),
+                };
+                (Difference::Different, children)
+            }
+            (Some(l), Some(r)) => (self.files(left, l, right, r)?, vec![]),
+            (None, None) => (Difference::Identical, vec![]),
+        };
+        Ok(ComparedEntry {
+            name: relative
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path: relative.to_owned(),
+            left: left_metadata.map(ComparedSide::from),
+            right: right_metadata.map(ComparedSide::from),
+            difference,
+            children,
+        })
+    }
+
+    /// Links are compared by their targets and special files such as FIFOs
+    /// only by their type, so they are never read. Files that can't be read
+    /// are different.
+    fn files(
+        &mut self,
+        left: &Path,
+        left_metadata: &fs::Metadata,
+        right: &Path,
+        right_metadata: &fs::Metadata,
+    ) -> Result<Difference, CurrentDirError> {
+        let same = |is_same: bool| match is_same {
+            true => Difference::Identical,
+            false => Difference::Different,
+        };
+        let is_link = |metadata: &fs::Metadata| metadata.file_type().is_symlink();
+        if is_link(left_metadata) || is_link(right_metadata) {
+            let targets = (fs::read_link(left).ok(), fs::read_link(right).ok());
+            return Ok(same(targets.0.is_some() && targets.0 == targets.1));
+        }
+        if !left_metadata.is_file() || !right_metadata.is_file() {
+            return Ok(same(
+                left_metadata.file_type() == right_metadata.file_type(),
+            ));
+        }
+        if left_metadata.len() != right_metadata.len() {
+            return Ok(Difference::Different);
+        }
+        match self.mode {
+            CompareMode::SizeAndModified => Ok(same(
+                platform::modified(left_metadata) == platform::modified(right_metadata),
+            )),
+            CompareMode::Contents => {
+                let algorithms = [HashAlgorithm::Blake3];
+                let hash = |path: &Path| match hash_file(
+                    path,
+                    &algorithms,
+                    u64::MAX,
+                    self.job,
+                    &self.read,
+                ) {
+                    Ok(mut hashes) => Ok(hashes.remove(&HashAlgorithm::Blake3)),
+                    Err(CurrentDirError::Cancelled) => Err(CurrentDirError::Cancelled),
+                    Err(_) => Ok(None),
+                };
+                let left_hash = hash(left)?;
+                let right_hash = hash(right)?;
+                Ok(same(left_hash.is_some() && left_hash == right_hash))
+            }
+        }
+    }
+}
+
+/// Everything under a folder that only exists on one side
+fn one_sided(path: &Path, relative: &Path, is_left: bool) -> Vec<ComparedEntry> {
+    list(path)
+        .into_iter()
+        .map(|(name, metadata)| {
+            let side = Some(ComparedSide::from(&metadata));
+            let children = match metadata.is_dir() {
+                true => one_sided(&path.join(&name), &relative.join(&name), is_left),
+                false => vec![],
+            };
+            ComparedEntry {
+                name: name.to_string_lossy().to_string(),
+                path: relative.join(&name),
+                left: side.filter(|_| is_left),
+                right: side.filter(|_| !is_left),
+                difference: match is_left {
+                    true => Difference::OnlyLeft,
+                    false => Difference::OnlyRight,
+                },
+                children,
+            }
+        })
+        .collect()
+}
+
+/// Compares everything under two folders by their relative paths
+pub fn compare(
+    left: &Path,
+    right: &Path,
+    mode: CompareMode,
+    job: &Job,
+    on_progress: impl FnMut(JobProgress),
+) -> Result<Comparison, CurrentDirError> {
+    for folder in [left, right] {
+        if !folder.is_dir() {
+            return Err(CurrentDirError::CannotReadDir {
+                dir_name: folder.to_string_lossy().to_string(),
+            });
+        }
+    }
+    let mut compare = Compare {
+        mode,
+        job,
+        read: Arc::new(AtomicU64::new(0)),
+        on_progress,
+    };
+    let entries = compare.folders(left, right, Path::new(""), true)?;
+    let mut summary = CompareSummary::default();
+    summary.count(&entries);
+    Ok(Comparison {
+        job_id: job.id,
+        left: left.to_owned(),
+        right: right.to_owned(),
+        mode,
+        summary,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, CompareMode, CompareSummary, Difference};
+    use crate::jobs::Job;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn folders_are_compared_by_relative_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        let (left, right) = (root.join("left"), root.join("right"));
+        for side in [&left, &right] {
+            fs::create_dir_all(side.join("docs")).unwrap();
+            fs::write(side.join("docs/same.txt"), "same").unwrap();
+        }
+        fs::write(left.join("edited.txt"), "before").unwrap();
+        fs::write(right.join("edited.txt"), "after!").unwrap();
+        fs::create_dir_all(left.join("old/nested")).unwrap();
+        fs::write(right.join("new.txt"), "new").unwrap();
+
+        let by_contents = compare(
+            &left,
+            &right,
+            CompareMode::Contents,
+            &Job::detached(),
+            |_| {},
+        )
+        .unwrap();
+        let by_size = compare(
+            &left,
+            &right,
+            CompareMode::SizeAndModified,
+            &Job::detached(),
+            |_| {},
+        )
+        .unwrap();
+
+        let differences = by_contents
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_path(), entry.difference))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            differences,
+            vec![
+                (Path::new("docs"), Difference::Identical),
+                (Path::new("edited.txt"), Difference::Different),
+                (Path::new("new.txt"), Difference::OnlyRight),
+                (Path::new("old"), Difference::OnlyLeft),
+            ]
+        );
+        assert_eq!(
+            by_contents.entries[3].children[0].path,
+            Path::new("old/nested")
+        );
+        assert_eq!(
+            by_contents.summary,
+            CompareSummary {
+                only_left: 2,
+                only_right: 1,
+                identical: 2,
+                different: 1,
+            }
+        );
+        assert_eq!(by_size.summary.only_left, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_are_compared_by_type() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        let (left, right) = (root.join("left"), root.join("right"));
+        for side in [&left, &right] {
+            fs::create_dir_all(side).unwrap();
+            nix::unistd::mkfifo(&side.join("pipe"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+        }
+        fs::write(left.join("queue"), "").unwrap();
+        nix::unistd::mkfifo(&right.join("queue"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+
+        let comparison = compare(
+            &left,
+            &right,
+            CompareMode::Contents,
+            &Job::detached(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(
+            comparison
+                .entries
+                .iter()
+                .map(|entry| (entry.path.as_path(), entry.difference))
+                .collect::<Vec<_>>(),
+            vec![
+                (Path::new("pipe"), Difference::Identical),
+                (Path::new("queue"), Difference::Different),
+            ]
+        );
+    }
+}
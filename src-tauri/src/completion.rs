@@ -0,0 +1,233 @@
+use super::{
+    vfs::{self, VfsBackend},
+    CurrentDir, CurrentDirError, FileData, FileType,
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+pub const MAX_COMPLETIONS: usize = 50;
+
+/// Expands a leading `~` and `$VAR` or `${VAR}` references. Unknown variables
+/// are left as they are.
+pub fn expand(partial: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(partial.len());
+    let mut rest = partial;
+
+    if rest == "~" || rest.starts_with("~/") {
+        if let Some(home) = lookup("HOME") {
+            expanded.push_str(&home);
+            rest = &rest[1..];
+        }
+    }
+
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, remaining) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => {
+                expanded.push_str(&value);
+                rest = remaining;
+            }
+            None => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Links to folders count as folders
+fn is_folder(backend: &dyn VfsBackend, path: &Path) -> bool {
+    backend
+        .canonicalize(path)
+        .and_then(|path| backend.stat(&path))
+        .is_ok_and(|metadata| metadata.filetype == FileType::Folder)
+}
+
+/// Walks up from `folder` until an existing folder is found. The name being
+/// typed becomes the first component that doesn't exist.
+fn split_existing(backend: &dyn VfsBackend, folder: &Path, typed: &str) -> (PathBuf, String) {
+    let mut ancestor = folder.to_path_buf();
+    let mut typed = typed.to_owned();
+
+    while !is_folder(backend, &ancestor) {
+        typed = ancestor
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match vfs::parent(&ancestor) {
+            Some(parent) => ancestor = parent,
+            None => break,
+        }
+    }
+    (ancestor, typed)
+}
+
+impl CurrentDir {
+    /// Returns the children of the deepest existing ancestor of `partial`
+    /// whose names start with the last typed component, ignoring case.
+    /// Relative paths are completed from the current directory.
+    pub fn complete_path(&self, partial: &str) -> Result<Vec<FileData>, CurrentDirError> {
+        let expanded = expand(partial, |name| env::var(name).ok());
+        let typed = expanded.rsplit('/').next().unwrap_or_default();
+        let typed_folder = Path::new(&expanded[..expanded.len() - typed.len()]);
+        let folder = match vfs::RemoteUrl::parse(typed_folder) {
+            Some(_) => typed_folder.to_owned(),
+            None => self.path.join(typed_folder),
+        };
+        let backend = self.backend_for(&folder)?;
+
+        let (ancestor, prefix) = split_existing(backend.as_ref(), &folder, typed);
+        let ancestor = self.canonicalize(&ancestor)?;
+        let prefix = prefix.to_lowercase();
+
+        let mut completions = backend
+            .list(&ancestor)
+            .map_err(|err| CurrentDirError::CannotReadDir {
+                dir_name: err.to_string(),
+            })?
+            .into_iter()
+            .filter_map(|entry| {
+                let name = entry.path.file_name()?.to_str()?.to_owned();
+                let lowercase = name.to_lowercase();
+                // Hidden files are only suggested once a dot has been typed
+                if !lowercase.starts_with(&prefix) || (name.starts_with('.') && prefix.is_empty()) {
+                    return None;
+                }
+                Some(FileData::new(&name, &entry.path, entry.metadata.filetype))
+            })
+            .collect::<Vec<FileData>>();
+
+        completions.sort_unstable();
+        completions.truncate(MAX_COMPLETIONS);
+        Ok(completions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, split_existing};
+    use crate::{
+        filecache::FileCache, memfs::MemoryBackend, vfs::LocalBackend, vfs::VfsBackend, CurrentDir,
+        FileType,
+    };
+    use std::{fs, path::Path, sync::Arc};
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some(String::from("/home/vili")),
+            "PROJECTS" => Some(String::from("/srv/projects")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn home_and_variables_are_expanded() {
+        assert_eq!(expand("~", lookup), "/home/vili");
+        assert_eq!(expand("~/Documents", lookup), "/home/vili/Documents");
+        assert_eq!(expand("~user/x", lookup), "~user/x");
+        assert_eq!(expand("$PROJECTS/mie", lookup), "/srv/projects/mie");
+        assert_eq!(
+            expand("${PROJECTS}x/$HOME", lookup),
+            "/srv/projectsx//home/vili"
+        );
+        assert_eq!(expand("$UNKNOWN/a$", lookup), "$UNKNOWN/a$");
+    }
+
+    #[test]
+    fn deepest_existing_ancestor_is_found() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("Documents")).unwrap();
+
+        let backend = LocalBackend::default();
+        let partial = split_existing(&backend, &root, "Doc");
+        let missing = split_existing(&backend, &root.join("nope").join("deeper"), "x");
+        let folder = split_existing(&backend, &root.join("Documents"), "");
+
+        assert_eq!(partial, (root.clone(), String::from("Doc")));
+        assert_eq!(missing, (root.clone(), String::from("nope")));
+        assert_eq!(folder, (root.join("Documents"), String::new()));
+        assert_eq!(
+            split_existing(&backend, Path::new("/"), ""),
+            (Path::new("/").to_owned(), String::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn children_are_matched_ignoring_case() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("Documents")).unwrap();
+        fs::write(root.join("docs.txt"), "").unwrap();
+        fs::write(root.join("music.txt"), "").unwrap();
+        fs::write(root.join(".dotfile"), "").unwrap();
+
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let current_dir = CurrentDir::new(&root, cache).unwrap();
+        let names = |partial: &str| {
+            current_dir
+                .complete_path(partial)
+                .unwrap()
+                .into_iter()
+                .map(|file| file.name)
+                .collect::<Vec<String>>()
+        };
+        let relative = names("doc");
+        let absolute = names(&format!("{}/DOC", root.display()));
+        let everything = names("");
+        let hidden = names(".");
+
+        assert_eq!(relative, vec!["Documents", "docs.txt"]);
+        assert_eq!(absolute, relative);
+        assert_eq!(everything, vec!["Documents", "docs.txt", "music.txt"]);
+        assert_eq!(hidden, vec![".dotfile"]);
+    }
+
+    #[tokio::test]
+    async fn completions_come_from_the_backend() {
+        let backend = MemoryBackend::new();
+        backend
+            .create(Path::new("/project"), FileType::Folder)
+            .unwrap();
+        backend
+            .create(Path::new("/project/src"), FileType::Folder)
+            .unwrap();
+        backend.write(Path::new("/project/setup.py"), b"").unwrap();
+
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let current_dir =
+            CurrentDir::with_backend(Path::new("/project"), cache, Arc::new(backend)).unwrap();
+        let names = current_dir
+            .complete_path("s")
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.name, file.filetype))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            names,
+            vec![
+                (String::from("src"), FileType::Folder),
+                (String::from("setup.py"), FileType::File)
+            ]
+        );
+    }
+}
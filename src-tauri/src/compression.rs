@@ -0,0 +1,335 @@
+use super::{
+    jobs::{Job, JobProgress, JobReader},
+    platform, CurrentDirError, FileData,
+};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use walkdir::WalkDir;
+use xz2::write::XzEncoder;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum CompressFormat {
+    Zip,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl CompressFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressFormat::Zip => "zip",
+            CompressFormat::TarGz => "tar.gz",
+            CompressFormat::TarXz => "tar.xz",
+            CompressFormat::TarZst => "tar.zst",
+        }
+    }
+
+    /// Levels go from 0 to 9, except for zstd which goes from 1 to 19. Levels
+    /// outside the range are clamped.
+    pub fn level(&self, level: Option<u32>) -> u32 {
+        match self {
+            CompressFormat::TarZst => level.unwrap_or(3).clamp(1, 19),
+            _ => level.unwrap_or(6).min(9),
+        }
+    }
+}
+
+/// A file or folder to add, with its path inside the archive
+struct Source {
+    path: PathBuf,
+    name: PathBuf,
+    metadata: fs::Metadata,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Compressed {
+    pub path: PathBuf,
+    /// Special files such as FIFOs, sockets and devices, which have no
+    /// contents to pack
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Selected folders are added with everything in them. Names inside the
+/// archive start from the selected entries, not from the root. Returns the
+/// sources and the special files that were left out.
+fn collect_sources(files: &[FileData]) -> Result<(Vec<Source>, Vec<PathBuf>), CurrentDirError> {
+    let mut sources = vec![];
+    let mut skipped = vec![];
+    for file in files {
+        let base = file.path.parent().unwrap_or(Path::new("/"));
+        for entry in WalkDir::new(&file.path).follow_links(false) {
+            let entry = entry.map_err(|err| CurrentDirError::CannotCompress {
+                reason: err.to_string(),
+            })?;
+            let metadata = entry
+                .metadata()
+                .map_err(|err| CurrentDirError::CannotCompress {
+                    reason: err.to_string(),
+                })?;
+            let filetype = metadata.file_type();
+            if !filetype.is_file() && !filetype.is_dir() && !filetype.is_symlink() {
+                skipped.push(entry.into_path());
+                continue;
+            }
+            sources.push(Source {
+                name: entry
+                    .path()
+                    .strip_prefix(base)
+                    .unwrap_or(entry.path())
+                    .to_owned(),
+                path: entry.into_path(),
+                metadata,
+            });
+        }
+    }
+    Ok((sources, skipped))
+}
+
+fn cancelled() -> io::Error {
+    io::Error::other("cancelled")
+}
+
+fn write_tar<W: Write>(
+    writer: W,
+    sources: &[Source],
+    job: &Job,
+    read: &Arc<AtomicU64>,
+    on_progress: &mut dyn FnMut(&Path),
+) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    for source in sources {
+        if job.is_cancelled() {
+            return Err(cancelled());
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&source.metadata);
+
+        if source.metadata.file_type().is_symlink() {
+            header.set_link_name(fs::read_link(&source.path)?)?;
+            builder.append_data(&mut header, &source.name, io::empty())?;
+        } else if source.metadata.is_dir() {
+            builder.append_data(&mut header, &source.name, io::empty())?;
+        } else {
+            let contents = JobReader::new(File::open(&source.path)?, job, read);
+            builder.append_data(&mut header, &source.name, contents)?;
+        }
+        on_progress(&source.path);
+    }
+    builder.into_inner()
+}
+
+fn write_zip(
+    file: File,
+    level: u32,
+    sources: &[Source],
+    job: &Job,
+    read: &Arc<AtomicU64>,
+    on_progress: &mut dyn FnMut(&Path),
+) -> io::Result<File> {
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .compression_level(Some(level as i32));
+
+    for source in sources {
+        if job.is_cancelled() {
+            return Err(cancelled());
+        }
+        let name = source.name.to_string_lossy();
+        let options = match platform::mode(&source.metadata) {
+            Some(mode) => options.unix_permissions(mode & 0o777),
+            None => options,
+        };
+
+        if source.metadata.file_type().is_symlink() {
+            let link = fs::read_link(&source.path)?;
+            zip.add_symlink(name, link.to_string_lossy(), options)?;
+        } else if source.metadata.is_dir() {
+            zip.add_directory(format!("{}/", name), options)?;
+        } else {
+            zip.start_file(
+                name,
+                options.large_file(source.metadata.len() >= u64::from(u32::MAX)),
+            )?;
+            io::copy(
+                &mut JobReader::new(File::open(&source.path)?, job, read),
+                &mut zip,
+            )?;
+        }
+        on_progress(&source.path);
+    }
+    Ok(zip.finish()?)
+}
+
+/// Packs the files into a new archive at `destination` as a cancellable
+/// job. The archive is written next to the destination first and only
+/// renamed into place once it is complete. Progress is the number of bytes
+/// of the files read so far. Special files are skipped.
+pub fn compress(
+    files: &[FileData],
+    destination: &Path,
+    format: CompressFormat,
+    level: Option<u32>,
+    job: &Job,
+    mut on_progress: impl FnMut(JobProgress),
+) -> Result<Compressed, CurrentDirError> {
+    if fs::symlink_metadata(destination).is_ok() {
+        return Err(CurrentDirError::CannotCompress {
+            reason: format!("{} already exists", destination.display()),
+        });
+    }
+    let (sources, skipped) = collect_sources(files)?;
+    let total = sources
+        .iter()
+        .filter(|source| source.metadata.is_file())
+        .map(|source| source.metadata.len())
+        .sum();
+    let level = format.level(level);
+    let read = Arc::new(AtomicU64::new(0));
+    let mut report = |path: &Path| {
+        on_progress(job.progress(read.load(Ordering::SeqCst), total, Some(path)));
+    };
+
+    let mut partial = destination.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let written = File::create(&partial).and_then(|file| match format {
+        CompressFormat::Zip => write_zip(file, level, &sources, job, &read, &mut report),
+        CompressFormat::TarGz => write_tar(
+            GzEncoder::new(file, Compression::new(level)),
+            &sources,
+            job,
+            &read,
+            &mut report,
+        )?
+        .finish(),
+        CompressFormat::TarXz => write_tar(
+            XzEncoder::new(file, level),
+            &sources,
+            job,
+            &read,
+            &mut report,
+        )?
+        .finish(),
+        CompressFormat::TarZst => write_tar(
+            zstd::Encoder::new(file, level as i32)?,
+            &sources,
+            job,
+            &read,
+            &mut report,
+        )?
+        .finish(),
+    });
+
+    match written.and_then(|_| fs::rename(&partial, destination)) {
+        Ok(()) => Ok(Compressed {
+            path: destination.to_owned(),
+            skipped,
+        }),
+        Err(err) => {
+            let _ = fs::remove_file(&partial);
+            Err(job.io_error(err, |reason| CurrentDirError::CannotCompress { reason }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, CompressFormat};
+    use crate::{
+        archive::{extract_archive, ArchiveIndex},
+        jobs::JobRegistry,
+        CurrentDirError, FileData, FileType,
+    };
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn compressed_folders_can_be_extracted() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        fs::write(root.join("project/src/main.rs"), "fn main() {}").unwrap();
+        std::os::unix::fs::symlink("src/main.rs", root.join("project/link")).unwrap();
+        nix::unistd::mkfifo(&root.join("project/pipe"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let selection = vec![FileData::new(
+            "project",
+            &root.join("project"),
+            FileType::Folder,
+        )];
+
+        let mut registry = JobRegistry::default();
+        let mut results = vec![];
+        for format in [
+            CompressFormat::Zip,
+            CompressFormat::TarGz,
+            CompressFormat::TarXz,
+            CompressFormat::TarZst,
+        ] {
+            let folder = root.join(format.extension());
+            fs::create_dir(&folder).unwrap();
+            let archive = folder.join(format!("project.{}", format.extension()));
+            let job = registry.start();
+            let mut last_progress = None;
+            let compressed = compress(&selection, &archive, format, Some(1), &job, |progress| {
+                last_progress = Some(progress)
+            })
+            .unwrap();
+            let extracted = extract_archive(&archive, None, &registry.start(), |_| {}).unwrap();
+            results.push((
+                last_progress.unwrap(),
+                ArchiveIndex::read(&archive)
+                    .unwrap()
+                    .is_dir("project/src".as_ref()),
+                fs::read_to_string(extracted.path.join("project/link")).unwrap(),
+                extracted.path.join("project/pipe").exists(),
+                compressed.skipped,
+                extracted.path,
+            ));
+        }
+
+        for (progress, has_folder, contents, has_pipe, skipped, path) in results {
+            assert_eq!((progress.done, progress.total), (12, 12));
+            assert!(has_folder);
+            assert_eq!(contents, "fn main() {}");
+            assert!(!has_pipe);
+            assert_eq!(skipped, vec![root.join("project/pipe")]);
+            assert_eq!(path.file_name().unwrap(), "project");
+        }
+    }
+
+    #[test]
+    fn cancelled_compression_leaves_nothing_behind() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::write(root.join("file"), "contents").unwrap();
+        let selection = vec![FileData::new("file", &root.join("file"), FileType::File)];
+
+        let mut registry = JobRegistry::default();
+        let job = registry.start();
+        registry.cancel(job.id).unwrap();
+        let result = compress(
+            &selection,
+            &root.join("file.zip"),
+            CompressFormat::Zip,
+            None,
+            &job,
+            |_| {},
+        );
+        let leftovers = fs::read_dir(&root).unwrap().count();
+
+        assert!(matches!(result, Err(CurrentDirError::Cancelled)));
+        assert_eq!(leftovers, 1);
+    }
+}
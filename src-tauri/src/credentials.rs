@@ -0,0 +1,35 @@
+use super::{vfs::RemoteUrl, CurrentDirError};
+
+const SERVICE: &str = "mielikki";
+
+/// Passwords are kept in the secret store of the desktop, one for every
+/// server and user, so that they never end up in the config files
+fn entry(url: &RemoteUrl) -> Result<keyring::Entry, CurrentDirError> {
+    keyring::Entry::new(SERVICE, &url.to_string()).map_err(|err| {
+        CurrentDirError::CannotStorePassword {
+            reason: err.to_string(),
+        }
+    })
+}
+
+pub fn save_password(url: &RemoteUrl, password: &str) -> Result<(), CurrentDirError> {
+    entry(url)?
+        .set_password(password)
+        .map_err(|err| CurrentDirError::CannotStorePassword {
+            reason: err.to_string(),
+        })
+}
+
+/// `None` when no password has been saved or the store can't be reached
+pub fn password(url: &RemoteUrl) -> Option<String> {
+    entry(url).ok()?.get_password().ok()
+}
+
+pub fn forget_password(url: &RemoteUrl) -> Result<(), CurrentDirError> {
+    match entry(url)?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(CurrentDirError::CannotStorePassword {
+            reason: err.to_string(),
+        }),
+    }
+}
@@ -0,0 +1,27 @@
+use tokio_rusqlite::Connection;
+
+/// Unlike the file cache, this database isn't rebuilt on every indexing
+/// round, so it holds everything the user creates
+pub const APP_DATABASE_FILE: &str = "mielikki_app.db";
+
+#[derive(Debug)]
+pub struct AppDatabase {
+    pub(crate) connection: Connection,
+}
+
+impl AppDatabase {
+    pub async fn open(path: &str) -> Result<Self, tokio_rusqlite::Error> {
+        AppDatabase::create_tables(Connection::open(path).await?).await
+    }
+
+    pub async fn open_in_memory() -> Result<Self, tokio_rusqlite::Error> {
+        AppDatabase::create_tables(Connection::open_in_memory().await?).await
+    }
+
+    async fn create_tables(connection: Connection) -> Result<Self, tokio_rusqlite::Error> {
+        connection
+            .call(|conn| conn.execute_batch(include_str!("../sql/create_app_tables.sql")))
+            .await?;
+        Ok(AppDatabase { connection })
+    }
+}
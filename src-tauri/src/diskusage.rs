@@ -0,0 +1,358 @@
+use super::{
+    database::AppDatabase,
+    jobs::{Job, JobId},
+    platform, CurrentDirError, FileType,
+};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Partial results are considered after this many entries even when the
+/// current top level folder is still being scanned
+const PROGRESS_INTERVAL: u64 = 5000;
+/// Partial results are sent at most this often
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+/// Partial results only have the largest top level entries
+const REPORTED_CHILDREN: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UsageEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub filetype: FileType,
+    /// Space taken on the disk, with everything inside for folders
+    pub size: u64,
+    /// The entry itself and everything inside it
+    pub items: u64,
+}
+
+/// A scanned folder with its direct children, largest first
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FolderUsage {
+    pub path: PathBuf,
+    pub size: u64,
+    pub items: u64,
+    /// Seconds since the unix epoch when the scan started
+    pub scanned: i64,
+    pub children: Vec<UsageEntry>,
+}
+
+/// Sent while a scan runs, with the largest top level entries that are done
+/// so far
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UsageProgress {
+    pub job_id: JobId,
+    pub size: u64,
+    pub items: u64,
+    pub children: Vec<UsageEntry>,
+    pub current: Option<PathBuf>,
+}
+
+fn sort_largest_first(entries: &mut [UsageEntry]) {
+    entries.sort_unstable_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+}
+
+struct Scan<'a, F> {
+    job: &'a Job,
+    /// Mount points under the root lead to other filesystems, which are
+    /// left out like with `du -x`
+    device: Option<u64>,
+    /// Files with more than one link are counted at the first link only
+    linked: HashSet<(u64, u64)>,
+    scanned: i64,
+    folders: Vec<FolderUsage>,
+    size: u64,
+    items: u64,
+    top_level: Vec<UsageEntry>,
+    last_report: Option<Instant>,
+    on_progress: F,
+}
+
+impl<F: FnMut(UsageProgress)> Scan<'_, F> {
+    /// Skipped when the previous report was sent less than
+    /// `REPORT_INTERVAL` ago
+    fn report(&mut self, current: Option<&Path>) {
+        if self
+            .last_report
+            .is_some_and(|last| last.elapsed() < REPORT_INTERVAL)
+        {
+            return;
+        }
+        self.last_report = Some(Instant::now());
+
+        let mut children = self.top_level.clone();
+        if children.len() > REPORTED_CHILDREN {
+            children.select_nth_unstable_by(REPORTED_CHILDREN, |a, b| b.size.cmp(&a.size));
+            children.truncate(REPORTED_CHILDREN);
+        }
+        sort_largest_first(&mut children);
+        (self.on_progress)(UsageProgress {
+            job_id: self.job.id,
+            size: self.size,
+            items: self.items,
+            children,
+            current: current.map(Path::to_owned),
+        });
+    }
+
+    fn entry(
+        &mut self,
+        path: &Path,
+        metadata: &fs::Metadata,
+    ) -> Result<UsageEntry, CurrentDirError> {
+        let is_counted = metadata.is_dir()
+            || platform::links(metadata).unwrap_or(1) < 2
+            || platform::file_id(metadata).is_none_or(|id| self.linked.insert(id));
+        let size = match is_counted {
+            true => platform::allocated(metadata),
+            false => 0,
+        };
+        self.size += size;
+        self.items += 1;
+        if self.items.is_multiple_of(PROGRESS_INTERVAL) {
+            self.job.check()?;
+            self.report(Some(path));
+        }
+
+        let (size, items) = match metadata.is_dir() {
+            true => self.folder(path, size, false)?,
+            false => (size, 1),
+        };
+        Ok(UsageEntry {
+            name: path
+                .file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .to_string(),
+            path: path.to_owned(),
+            filetype: FileType::from(metadata.file_type()),
+            size,
+            items,
+        })
+    }
+
+    /// Records the folder and returns its size and item count. Unreadable
+    /// folders count as empty.
+    fn folder(
+        &mut self,
+        path: &Path,
+        own_size: u64,
+        is_top_level: bool,
+    ) -> Result<(u64, u64), CurrentDirError> {
+        let mut children = vec![];
+        let entries = fs::read_dir(path).into_iter().flatten().flatten();
+        for entry in entries {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if platform::device(&metadata) != self.device {
+                continue;
+            }
+            let child = self.entry(&entry.path(), &metadata)?;
+            if is_top_level {
+                self.top_level.push(child.clone());
+                self.job.check()?;
+                self.report(None);
+            }
+            children.push(child);
+        }
+
+        let size = own_size + children.iter().map(|child| child.size).sum::<u64>();
+        let items = 1 + children.iter().map(|child| child.items).sum::<u64>();
+        sort_largest_first(&mut children);
+        self.folders.push(FolderUsage {
+            path: path.to_owned(),
+            size,
+            items,
+            scanned: self.scanned,
+            children,
+        });
+        Ok((size, items))
+    }
+}
+
+/// Finds how much space every folder under `root` takes. Partial results
+/// are sent as top level entries get done, at most every `REPORT_INTERVAL`.
+/// Returns all the folders, the root last. The paths start from the
+/// canonical root, which is what the scan should be saved under.
+pub fn analyze(
+    root: &Path,
+    job: &Job,
+    on_progress: impl FnMut(UsageProgress),
+) -> Result<Vec<FolderUsage>, CurrentDirError> {
+    let root = &fs::canonicalize(root).map_err(|_| CurrentDirError::PathCannotBeMadeAbsolute)?;
+    let metadata = fs::metadata(root).map_err(|_| CurrentDirError::CannotReadMetadata {
+        path: root.to_string_lossy().to_string(),
+    })?;
+    if !metadata.is_dir() {
+        return Err(CurrentDirError::CannotReadDir {
+            dir_name: root.to_string_lossy().to_string(),
+        });
+    }
+    let scanned = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64);
+
+    let mut scan = Scan {
+        job,
+        device: platform::device(&metadata),
+        linked: HashSet::new(),
+        scanned,
+        folders: vec![],
+        size: platform::allocated(&metadata),
+        items: 1,
+        top_level: vec![],
+        last_report: None,
+        on_progress,
+    };
+    scan.folder(root, platform::allocated(&metadata), true)?;
+    Ok(scan.folders)
+}
+
+impl AppDatabase {
+    /// Replaces the earlier results for `root` and everything under it
+    pub async fn save_disk_usage(
+        &self,
+        root: &Path,
+        folders: Vec<FolderUsage>,
+    ) -> Result<(), CurrentDirError> {
+        let root = root.to_string_lossy().to_string();
+        let rows = folders
+            .into_iter()
+            .map(|folder| {
+                let children = serde_json::to_string(&folder.children)
+                    .map_err(|_| CurrentDirError::CannotSerialize)?;
+                Ok((
+                    folder.path.to_string_lossy().to_string(),
+                    folder.size as i64,
+                    folder.items as i64,
+                    folder.scanned,
+                    children,
+                ))
+            })
+            .collect::<Result<Vec<_>, CurrentDirError>>()?;
+
+        self.connection
+            .call(move |conn| {
+                let transaction = conn.transaction()?;
+                let prefix = format!("{}/", root.trim_end_matches('/'));
+                transaction.execute(
+                    "DELETE FROM disk_usage
+                     WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+                    (&root, &prefix),
+                )?;
+                for row in rows {
+                    transaction.execute(
+                        "INSERT INTO disk_usage (path, size, items, scanned, children)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        row,
+                    )?;
+                }
+                transaction.commit()
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// `None` when the folder hasn't been scanned
+    pub async fn get_disk_usage(
+        &self,
+        folder: &Path,
+    ) -> Result<Option<FolderUsage>, CurrentDirError> {
+        let path = folder.to_owned();
+        let folder = folder.to_string_lossy().to_string();
+        let stored = self
+            .connection
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT size, items, scanned, children FROM disk_usage WHERE path = ?1",
+                    [folder],
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, i64>(1)?,
+                            row.get::<_, i64>(2)?,
+                            row.get::<_, String>(3)?,
+                        ))
+                    },
+                )
+                .optional()
+            })
+            .await?;
+
+        Ok(stored.and_then(|(size, items, scanned, children)| {
+            Some(FolderUsage {
+                path,
+                size: size as u64,
+                items: items as u64,
+                scanned,
+                children: serde_json::from_str(&children).ok()?,
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::analyze;
+    use crate::{database::AppDatabase, jobs::Job, platform};
+    use std::fs;
+
+    #[tokio::test]
+    async fn hard_links_are_counted_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(temp.path()).unwrap();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::write(root.join("a/data.bin"), vec![1; 64 * 1024]).unwrap();
+        fs::hard_link(root.join("a/data.bin"), root.join("b/data.bin")).unwrap();
+
+        let mut reports = 0;
+        let folders = analyze(&root.join("a/.."), &Job::detached(), |_| reports += 1).unwrap();
+        let scanned = folders.last().unwrap();
+        let link_sizes = folders
+            .iter()
+            .filter(|folder| folder.path != root)
+            .map(|folder| folder.children[0].size)
+            .collect::<Vec<u64>>();
+
+        // Usually one, as the second folder is done right after the first
+        // one, but a slow machine can take longer than `REPORT_INTERVAL`
+        assert!(reports >= 1);
+        assert_eq!(scanned.path, root);
+        assert_eq!(scanned.items, 5);
+        assert_eq!(
+            scanned.size,
+            platform::allocated(&fs::metadata(&root).unwrap())
+                + scanned.children.iter().map(|child| child.size).sum::<u64>()
+        );
+        assert!(link_sizes.contains(&0));
+        assert!(link_sizes.iter().any(|size| *size >= 64 * 1024));
+
+        let database = AppDatabase::open_in_memory().await.unwrap();
+        database
+            .save_disk_usage(&root, folders.clone())
+            .await
+            .unwrap();
+        database
+            .save_disk_usage(&root, folders.clone())
+            .await
+            .unwrap();
+        let stored = database.get_disk_usage(&root.join("a")).await.unwrap();
+
+        assert_eq!(
+            stored.as_ref(),
+            folders.iter().find(|folder| folder.path == root.join("a"))
+        );
+        assert!(database
+            .get_disk_usage(&root.join("missing"))
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,431 @@
+use super::{
+    database::AppDatabase,
+    hashing::{hash_file, HashAlgorithm},
+    jobs::{Job, JobId, JobProgress},
+    platform, CurrentDirError,
+};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    hash::Hash,
+    path::PathBuf,
+    sync::{atomic::AtomicU64, Arc},
+};
+use walkdir::WalkDir;
+
+/// Files of the same size are first told apart by hashing this much of
+/// their beginning, which rules out most of them without reading them whole
+const PARTIAL_SIZE: u64 = 16 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DuplicateOptions {
+    /// Hard links to the same file take no extra space, so they are listed
+    /// once instead of as copies of each other
+    pub hard_links_are_same: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    /// BLAKE3 of the contents in hex
+    pub hash: String,
+    pub paths: Vec<PathBuf>,
+    /// Space freed by keeping only one of the copies. Hard links to the same
+    /// file free nothing, so they count as one copy.
+    pub reclaimable: u64,
+}
+
+/// Groups with the most space to free come first
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Duplicates {
+    pub job_id: JobId,
+    pub groups: Vec<DuplicateGroup>,
+    pub reclaimable: u64,
+}
+
+/// A file whose hashes are still valid as long as none of these change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileKey {
+    pub device: u64,
+    pub inode: u64,
+    pub size: u64,
+    /// Nanoseconds since the unix epoch
+    pub modified: i64,
+}
+
+impl FileKey {
+    /// `None` on systems without inodes, where files can't be recognized
+    /// after a rename
+    fn of(metadata: &fs::Metadata) -> Option<Self> {
+        let (device, inode) = platform::file_id(metadata)?;
+        Some(FileKey {
+            device,
+            inode,
+            size: metadata.len(),
+            modified: platform::modified_nanos(metadata),
+        })
+    }
+}
+
+/// The full hash is only known for files that had a match for the partial
+/// one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    pub partial: String,
+    pub full: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    path: PathBuf,
+    size: u64,
+    /// Hashes are only saved for files that have a key
+    key: Option<FileKey>,
+}
+
+/// Empty files are left out, removing them frees nothing
+fn same_size_files(
+    roots: &[PathBuf],
+    options: DuplicateOptions,
+    job: &Job,
+) -> Result<Vec<Vec<Candidate>>, CurrentDirError> {
+    let mut files = vec![];
+    for root in roots {
+        let root = fs::canonicalize(root).map_err(|_| CurrentDirError::CannotReadMetadata {
+            path: root.to_string_lossy().to_string(),
+        })?;
+        for entry in WalkDir::new(&root)
+            .follow_links(false)
+            .into_iter()
+            .flatten()
+        {
+            job.check()?;
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() && metadata.len() > 0 {
+                files.push(Candidate {
+                    path: entry.into_path(),
+                    size: metadata.len(),
+                    key: FileKey::of(&metadata),
+                });
+            }
+        }
+    }
+
+    // Roots inside other roots would list their files twice
+    files.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    files.dedup_by(|a, b| a.path == b.path);
+    if options.hard_links_are_same {
+        let mut seen = HashSet::new();
+        files.retain(|file| {
+            file.key
+                .is_none_or(|key| seen.insert((key.device, key.inode)))
+        });
+    }
+    Ok(split_by(files, |file| Some(file.size)))
+}
+
+/// Groups the files by `key`, leaving out the ones without a key and the
+/// groups with only one file
+fn split_by<K: Hash + Eq>(
+    files: Vec<Candidate>,
+    mut key: impl FnMut(&Candidate) -> Option<K>,
+) -> Vec<Vec<Candidate>> {
+    let mut groups: HashMap<K, Vec<Candidate>> = HashMap::new();
+    for file in files {
+        if let Some(key) = key(&file) {
+            groups.entry(key).or_default().push(file);
+        }
+    }
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect()
+}
+
+/// Reports the bytes of every file as done once it has been either ruled out
+/// or fully hashed. Returns the hashes that weren't in `hashes` yet.
+fn compare(
+    groups: Vec<Vec<Candidate>>,
+    mut hashes: HashMap<FileKey, FileHashes>,
+    job: &Job,
+    mut on_progress: impl FnMut(JobProgress),
+) -> Result<(Duplicates, HashMap<FileKey, FileHashes>), CurrentDirError> {
+    let total = groups.iter().flatten().map(|file| file.size).sum();
+    let read = Arc::new(AtomicU64::new(0));
+    let mut done = 0;
+    let mut changed = HashSet::new();
+
+    // Files that can't be read are left out instead of failing the job
+    let mut hash = |file: &Candidate, full: bool| -> Result<Option<String>, CurrentDirError> {
+        let cached = file.key.and_then(|key| hashes.get(&key));
+        let cached = match full {
+            true => cached.and_then(|hashes| hashes.full.clone()),
+            false => cached.map(|hashes| hashes.partial.clone()),
+        };
+        if cached.is_some() {
+            return Ok(cached);
+        }
+        let limit = if full { u64::MAX } else { PARTIAL_SIZE };
+        let hash = match hash_file(&file.path, &[HashAlgorithm::Blake3], limit, job, &read) {
+            Ok(mut hashes) => hashes.remove(&HashAlgorithm::Blake3).unwrap_or_default(),
+            Err(CurrentDirError::Cancelled) => return Err(CurrentDirError::Cancelled),
+            Err(_) => return Ok(None),
+        };
+        let Some(key) = file.key else {
+            return Ok(Some(hash));
+        };
+        match (hashes.get_mut(&key), full) {
+            (Some(hashes), true) => hashes.full = Some(hash.clone()),
+            (Some(hashes), false) => hashes.partial = hash.clone(),
+            (None, _) => {
+                hashes.insert(
+                    key,
+                    FileHashes {
+                        partial: hash.clone(),
+                        full: None,
+                    },
+                );
+            }
+        }
+        changed.insert(key);
+        Ok(Some(hash))
+    };
+
+    let mut found = vec![];
+    for group in groups {
+        let size = group[0].size;
+        let count = group.len() as u64;
+        let mut partial_hashes = HashMap::new();
+        for file in &group {
+            job.check()?;
+            partial_hashes.insert(file.path.clone(), hash(file, false)?);
+        }
+        let partial_groups = split_by(group, |file| partial_hashes[&file.path].clone());
+
+        let mut matched = 0;
+        for partial_group in partial_groups {
+            matched += partial_group.len() as u64;
+            let mut full_hashes = HashMap::new();
+            for file in &partial_group {
+                job.check()?;
+                let full_hash = match size <= PARTIAL_SIZE {
+                    true => partial_hashes[&file.path].clone(),
+                    false => hash(file, true)?,
+                };
+                full_hashes.insert(file.path.clone(), full_hash);
+                done += size;
+                on_progress(job.progress(done, total, Some(&file.path)));
+            }
+            for duplicates in split_by(partial_group, |file| full_hashes[&file.path].clone()) {
+                let mut paths = duplicates
+                    .iter()
+                    .map(|file| file.path.clone())
+                    .collect::<Vec<PathBuf>>();
+                paths.sort_unstable();
+                // Hard links share the device and inode. Files without a key
+                // are told apart by their paths.
+                let copies = duplicates
+                    .iter()
+                    .map(|file| {
+                        file.key
+                            .map(|key| (key.device, key.inode))
+                            .ok_or(&file.path)
+                    })
+                    .collect::<HashSet<_>>()
+                    .len() as u64;
+                found.push(DuplicateGroup {
+                    size,
+                    hash: full_hashes[&paths[0]].clone().unwrap_or_default(),
+                    reclaimable: size * (copies - 1),
+                    paths,
+                });
+            }
+        }
+        done += size * (count - matched);
+        on_progress(job.progress(done, total, None));
+    }
+
+    found.sort_unstable_by(|a, b| {
+        b.reclaimable
+            .cmp(&a.reclaimable)
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+    let changed = hashes
+        .into_iter()
+        .filter(|(key, _)| changed.contains(key))
+        .collect();
+    Ok((
+        Duplicates {
+            job_id: job.id,
+            reclaimable: found.iter().map(|group| group.reclaimable).sum(),
+            groups: found,
+        },
+        changed,
+    ))
+}
+
+/// Finds files with the same contents under `roots`. Hashes are saved, so
+/// files that haven't changed since the last search aren't read again.
+pub async fn find_duplicates(
+    database: &AppDatabase,
+    roots: Vec<PathBuf>,
+    options: DuplicateOptions,
+    job: Job,
+    on_progress: impl FnMut(JobProgress) + Send + 'static,
+) -> Result<Duplicates, CurrentDirError> {
+    let walking_job = job.clone();
+    let groups =
+        tokio::task::spawn_blocking(move || same_size_files(&roots, options, &walking_job))
+            .await
+            .map_err(|_| CurrentDirError::Cancelled)??;
+
+    let keys = groups
+        .iter()
+        .flatten()
+        .filter_map(|file| file.key)
+        .collect();
+    let hashes = database.get_file_hashes(keys).await?;
+    let (duplicates, changed) =
+        tokio::task::spawn_blocking(move || compare(groups, hashes, &job, on_progress))
+            .await
+            .map_err(|_| CurrentDirError::Cancelled)??;
+    database.save_file_hashes(changed).await?;
+    Ok(duplicates)
+}
+
+impl AppDatabase {
+    /// Files that have changed since they were hashed are left out
+    pub async fn get_file_hashes(
+        &self,
+        keys: Vec<FileKey>,
+    ) -> Result<HashMap<FileKey, FileHashes>, CurrentDirError> {
+        Ok(self
+            .connection
+            .call(move |conn| {
+                let mut statement = conn.prepare(
+                    "SELECT partial, full FROM file_hashes
+                     WHERE device = ?1 AND inode = ?2 AND size = ?3 AND modified = ?4",
+                )?;
+                let mut hashes = HashMap::new();
+                for key in keys {
+                    let found = statement
+                        .query_row(
+                            (
+                                key.device as i64,
+                                key.inode as i64,
+                                key.size as i64,
+                                key.modified,
+                            ),
+                            |row| {
+                                Ok(FileHashes {
+                                    partial: row.get(0)?,
+                                    full: row.get(1)?,
+                                })
+                            },
+                        )
+                        .optional()?;
+                    if let Some(found) = found {
+                        hashes.insert(key, found);
+                    }
+                }
+                Ok(hashes)
+            })
+            .await?)
+    }
+
+    /// Replaces the hashes of earlier versions of the same files
+    pub async fn save_file_hashes(
+        &self,
+        hashes: HashMap<FileKey, FileHashes>,
+    ) -> Result<(), CurrentDirError> {
+        self.connection
+            .call(move |conn| {
+                let transaction = conn.transaction()?;
+                for (key, hashes) in hashes {
+                    transaction.execute(
+                        "DELETE FROM file_hashes WHERE device = ?1 AND inode = ?2",
+                        (key.device as i64, key.inode as i64),
+                    )?;
+                    transaction.execute(
+                        "INSERT INTO file_hashes (device, inode, size, modified, partial, full)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        (
+                            key.device as i64,
+                            key.inode as i64,
+                            key.size as i64,
+                            key.modified,
+                            hashes.partial,
+                            hashes.full,
+                        ),
+                    )?;
+                }
+                transaction.commit()
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_duplicates, DuplicateOptions, PARTIAL_SIZE};
+    use crate::{database::AppDatabase, jobs::Job};
+    use std::{fs, path::PathBuf};
+
+    #[tokio::test]
+    async fn copies_are_found_by_contents() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("copies")).unwrap();
+        let mut big = vec![7; PARTIAL_SIZE as usize * 2];
+        fs::write(root.join("big.bin"), &big).unwrap();
+        fs::write(root.join("copies/big.bin"), &big).unwrap();
+        // Only differs after the part that is hashed first
+        *big.last_mut().unwrap() = 8;
+        fs::write(root.join("almost.bin"), &big).unwrap();
+        fs::write(root.join("small.txt"), "hello").unwrap();
+        fs::write(root.join("other.txt"), "world").unwrap();
+        fs::write(root.join("copies/small.txt"), "hello").unwrap();
+        fs::hard_link(root.join("small.txt"), root.join("link.txt")).unwrap();
+        let database = AppDatabase::open_in_memory().await.unwrap();
+        let roots = vec![root.clone(), root.join("copies")];
+
+        let found = find_duplicates(
+            &database,
+            roots.clone(),
+            DuplicateOptions::default(),
+            Job::detached(),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        let links_as_one = find_duplicates(
+            &database,
+            roots,
+            DuplicateOptions {
+                hard_links_are_same: true,
+            },
+            Job::detached(),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        let paths =
+            |names: &[&str]| -> Vec<PathBuf> { names.iter().map(|name| root.join(name)).collect() };
+        assert_eq!(found.groups.len(), 2);
+        assert_eq!(found.groups[0].paths, paths(&["big.bin", "copies/big.bin"]));
+        assert_eq!(
+            found.groups[1].paths,
+            paths(&["copies/small.txt", "link.txt", "small.txt"])
+        );
+        assert_eq!(found.reclaimable, PARTIAL_SIZE * 2 + 5);
+        assert_eq!(links_as_one.groups[1].paths.len(), 2);
+        assert_eq!(links_as_one.reclaimable, PARTIAL_SIZE * 2 + 5);
+        assert_eq!(links_as_one.groups[0].hash, found.groups[0].hash);
+    }
+}
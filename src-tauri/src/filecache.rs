@@ -1,7 +1,17 @@
 use super::FileData;
 use rusqlite::{backup, named_params};
-use std::{path::Path, sync::Arc};
-use tokio::sync::Mutex;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{watch, Mutex},
+    task::JoinHandle,
+};
 use tokio_rusqlite::Connection;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,16 +33,29 @@ impl CachedFile {
     }
 }
 
-fn cache_files_on_interval(cache: Arc<FileCache>) {
-    tokio::spawn(async move {
-        let secondary_cache = FileCache::create_secondary().await;
-        loop {
-            // TODO, handle errors
-            secondary_cache.cache_all_files().await.unwrap();
-            secondary_cache.backup_database_to_file().await.unwrap();
-            cache.read_database_from_backup().await.unwrap();
-        }
-    });
+/// Handle to the background task that keeps re-indexing the file system.
+/// Indexing stops only when the handle is shut down, dropping it leaves the
+/// task running.
+#[derive(Debug)]
+pub struct Indexer {
+    shutdown: watch::Sender<bool>,
+    /// Checked between the entries of the walk, which runs on the database
+    /// thread where the shutdown message can't reach it
+    stopped: Arc<AtomicBool>,
+    task: JoinHandle<()>,
+}
+
+impl Indexer {
+    /// An indexing round that is still running is thrown away, so the cache
+    /// keeps the results of the last finished one
+    pub fn shutdown(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        let _ = self.shutdown.send(true);
+    }
+
+    pub async fn join(self) {
+        let _ = self.task.await;
+    }
 }
 
 #[derive(Debug)]
@@ -40,6 +63,9 @@ pub struct FileCache {
     database: Mutex<Connection>,
 }
 const DATABASE_FILE: &str = "mielikki.db";
+/// How long to wait before the next round after a failed one, so that a
+/// round that keeps failing doesn't spin
+const RETRY_DELAY: Duration = Duration::from_secs(30);
 
 impl FileCache {
     pub async fn new() -> Arc<Self> {
@@ -51,14 +77,69 @@ impl FileCache {
 
         if first_time {
             cache.create_cache_table().await.unwrap();
-            cache.cache_all_files().await.unwrap();
+            cache
+                .cache_all_files(Arc::new(AtomicBool::new(false)))
+                .await
+                .unwrap();
             cache.backup_database_to_file().await.unwrap();
         } else {
             cache.read_database_from_backup().await.unwrap();
         }
-        let data = Arc::new(cache);
-        cache_files_on_interval(Arc::clone(&data));
-        data
+        Arc::new(cache)
+    }
+
+    /// Starts re-indexing the file system in the background. Only one
+    /// indexer should be running per cache. Failed rounds are passed to
+    /// `on_error` and the next round is started after `RETRY_DELAY`.
+    pub fn start_indexing(
+        self: &Arc<Self>,
+        on_error: impl Fn(tokio_rusqlite::Error) + Send + 'static,
+    ) -> Indexer {
+        let (shutdown, mut shutdown_requested) = watch::channel(false);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let cache = Arc::clone(self);
+        let walk_stopped = Arc::clone(&stopped);
+
+        let task = tokio::spawn(async move {
+            let secondary_cache = FileCache::create_secondary().await;
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = shutdown_requested.changed() => break,
+                    result = cache.refresh_from(&secondary_cache, &walk_stopped) => {
+                        if let Err(err) = result {
+                            on_error(err);
+                            tokio::select! {
+                                biased;
+                                _ = shutdown_requested.changed() => break,
+                                _ = tokio::time::sleep(RETRY_DELAY) => {}
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Indexer {
+            shutdown,
+            stopped,
+            task,
+        }
+    }
+
+    /// Indexes into `secondary` so that searches on this cache aren't
+    /// blocked while the file system is walked. A stopped walk is thrown
+    /// away.
+    async fn refresh_from(
+        &self,
+        secondary: &FileCache,
+        stopped: &Arc<AtomicBool>,
+    ) -> Result<(), tokio_rusqlite::Error> {
+        if !secondary.cache_all_files(Arc::clone(stopped)).await? {
+            return Ok(());
+        }
+        secondary.backup_database_to_file().await?;
+        self.read_database_from_backup().await
     }
 
     pub async fn create_secondary() -> Self {
@@ -109,8 +190,12 @@ impl FileCache {
         asd.ok()
     }
 
-    /// Should be called only when initializing the database for the first time
-    async fn cache_all_files(&self) -> Result<(), tokio_rusqlite::Error> {
+    /// Replaces the contents of the cache with a walk of the whole file
+    /// system. Returns false when `stopped` was set before the walk finished.
+    async fn cache_all_files(
+        &self,
+        stopped: Arc<AtomicBool>,
+    ) -> Result<bool, tokio_rusqlite::Error> {
         self.clear_database().await?;
         self.create_cache_table().await?;
         let db = self.database.lock().await;
@@ -123,12 +208,15 @@ impl FileCache {
                 .enumerate()
                 .map(|(i, filedata)| CachedFile::from_filedata(filedata, i as i32))
             {
+                if stopped.load(Ordering::SeqCst) {
+                    return Ok(false);
+                }
                 conn.execute(
                     "INSERT INTO file_cache (id, name, path, filetype) VALUES (?1, ?2, ?3, ?4)",
                     (entry.id, entry.name, entry.path, entry.filetype),
                 )?;
             }
-            Ok(())
+            Ok(true)
         })
         .await
     }
@@ -193,9 +281,21 @@ impl FileCache {
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use std::{path::Path, sync::Arc};
+
+    use crate::{
+        filecache::{CachedFile, FileCache},
+        FileData,
+    };
 
-    use crate::{filecache::CachedFile, FileData};
+    #[tokio::test]
+    async fn indexer_shuts_down() {
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let indexer = cache.start_indexing(|err| panic!("{}", err));
+
+        indexer.shutdown();
+        indexer.join().await;
+    }
 
     #[test]
     fn cachedfile_from_filedata() {
@@ -203,6 +303,9 @@ mod tests {
             name: String::from("test"),
             path: Path::new("/test/path").to_owned(),
             filetype: crate::FileType::File,
+            mime: None,
+            size: None,
+            modified: None,
         };
 
         assert_eq!(
@@ -0,0 +1,222 @@
+use super::{database::AppDatabase, CurrentDirError, FileData, FileType};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// When the ranks add up to more than this, every rank is aged so that
+/// locations that aren't used anymore eventually drop out
+const MAX_TOTAL_RANK: f64 = 10_000.0;
+const AGING_FACTOR: f64 = 0.9;
+const HOUR: i64 = 60 * 60;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Scores like zoxide does: the rank grows by one per visit, and recent
+/// visits weigh more
+pub fn frecency(rank: f64, last_accessed: i64, now: i64) -> f64 {
+    let age = now - last_accessed;
+    if age < HOUR {
+        rank * 4.0
+    } else if age < DAY {
+        rank * 2.0
+    } else if age < WEEK {
+        rank * 0.5
+    } else {
+        rank * 0.25
+    }
+}
+
+/// Every word of the query has to appear in the path in order, and the last
+/// one has to be in the last component, eg. `pro mie` matches
+/// `/home/vili/projects/mielikki`
+pub fn matches(path: &Path, query: &str) -> bool {
+    let path = path.to_string_lossy().to_lowercase();
+    let words = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>();
+    let Some(last_word) = words.last() else {
+        return false;
+    };
+
+    let last_component = path.rsplit('/').next().unwrap_or_default();
+    if !last_component.contains(last_word.as_str()) {
+        return false;
+    }
+
+    let mut rest = path.as_str();
+    for word in &words {
+        match rest.find(word.as_str()) {
+            Some(index) => rest = &rest[index + word.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Moves the results with a score before the rest, keeping the order of
+/// equally scored results
+pub fn boost(results: &mut [FileData], scores: &HashMap<PathBuf, f64>) {
+    results.sort_by(|a, b| {
+        let score_a = scores.get(&a.path).copied().unwrap_or(0.0);
+        let score_b = scores.get(&b.path).copied().unwrap_or(0.0);
+        score_b.total_cmp(&score_a)
+    });
+}
+
+impl AppDatabase {
+    pub async fn record_visit(
+        &self,
+        path: &Path,
+        filetype: FileType,
+    ) -> Result<(), CurrentDirError> {
+        let path = path.to_string_lossy().to_string();
+        let now = now();
+
+        Ok(self
+            .connection
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO visits (path, filetype, rank, last_accessed)
+                     VALUES (?1, ?2, 1, ?3)
+                     ON CONFLICT (path) DO UPDATE SET rank = rank + 1, last_accessed = ?3",
+                    (path, filetype.to_string(), now),
+                )?;
+
+                let total: f64 =
+                    conn.query_row("SELECT COALESCE(SUM(rank), 0) FROM visits", [], |row| {
+                        row.get(0)
+                    })?;
+                if total > MAX_TOTAL_RANK {
+                    conn.execute("UPDATE visits SET rank = rank * ?1", [AGING_FACTOR])?;
+                    conn.execute("DELETE FROM visits WHERE rank < 1", [])?;
+                }
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn frecency_scores(&self) -> Result<HashMap<PathBuf, f64>, CurrentDirError> {
+        let now = now();
+        Ok(self
+            .connection
+            .call(move |conn| {
+                let mut statement = conn.prepare("SELECT path, rank, last_accessed FROM visits")?;
+                let scores = statement
+                    .query_map([], |row| {
+                        Ok((
+                            PathBuf::from(row.get::<_, String>(0)?),
+                            frecency(row.get(1)?, row.get(2)?, now),
+                        ))
+                    })?
+                    .collect::<Result<HashMap<PathBuf, f64>, rusqlite::Error>>()?;
+                Ok(scores)
+            })
+            .await?)
+    }
+
+    /// Resolves a partial query to the best scoring folder that still exists
+    pub async fn jump(&self, query: &str) -> Result<PathBuf, CurrentDirError> {
+        let now = now();
+        let folders = self
+            .connection
+            .call(|conn| {
+                let mut statement = conn.prepare(
+                    "SELECT path, rank, last_accessed FROM visits WHERE filetype = 'Folder'",
+                )?;
+                let folders = statement
+                    .query_map([], |row| {
+                        Ok((
+                            PathBuf::from(row.get::<_, String>(0)?),
+                            row.get::<_, f64>(1)?,
+                            row.get::<_, i64>(2)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<(PathBuf, f64, i64)>, rusqlite::Error>>()?;
+                Ok(folders)
+            })
+            .await?;
+
+        folders
+            .into_iter()
+            .filter(|(path, _, _)| matches(path, query) && path.is_dir())
+            .map(|(path, rank, last_accessed)| (path, frecency(rank, last_accessed, now)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(path, _)| path)
+            .ok_or(CurrentDirError::NoJumpTarget {
+                query: query.to_owned(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{boost, frecency, matches, DAY, HOUR};
+    use crate::{database::AppDatabase, FileData, FileType};
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    };
+
+    #[test]
+    fn recent_visits_weigh_more() {
+        let now = 1_000_000;
+
+        assert_eq!(frecency(2.0, now - 10, now), 8.0);
+        assert_eq!(frecency(2.0, now - 2 * HOUR, now), 4.0);
+        assert_eq!(frecency(2.0, now - 2 * DAY, now), 1.0);
+        assert_eq!(frecency(2.0, now - 30 * DAY, now), 0.5);
+    }
+
+    #[test]
+    fn query_matches_in_order() {
+        let path = Path::new("/home/vili/Projects/mielikki");
+
+        assert!(matches(path, "mie"));
+        assert!(matches(path, "proj MIE"));
+        assert!(!matches(path, "mie proj"));
+        assert!(!matches(path, "proj"));
+        assert!(!matches(path, ""));
+    }
+
+    #[test]
+    fn scored_results_are_boosted() {
+        let mut results = vec![
+            FileData::new("a", Path::new("/a"), FileType::File),
+            FileData::new("b", Path::new("/b"), FileType::File),
+            FileData::new("c", Path::new("/c"), FileType::File),
+        ];
+        let scores = HashMap::from([(PathBuf::from("/c"), 3.0)]);
+
+        boost(&mut results, &scores);
+
+        assert_eq!(results[0].path, Path::new("/c"));
+        assert_eq!(results[1].path, Path::new("/a"));
+    }
+
+    #[tokio::test]
+    async fn jump_prefers_frequent_folders() {
+        let database = AppDatabase::open_in_memory().await.unwrap();
+        let tmp = std::env::temp_dir();
+        let home = Path::new("/");
+        database.record_visit(&tmp, FileType::Folder).await.unwrap();
+        database.record_visit(&tmp, FileType::Folder).await.unwrap();
+        database.record_visit(home, FileType::Folder).await.unwrap();
+
+        let scores = database.frecency_scores().await.unwrap();
+        let query = tmp.file_name().unwrap().to_string_lossy().to_string();
+
+        assert_eq!(scores[&tmp], 8.0);
+        assert_eq!(database.jump(&query).await.unwrap(), tmp);
+        assert!(database.jump("nothing matches this").await.is_err());
+    }
+}
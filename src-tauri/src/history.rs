@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+pub const HISTORY_LIMIT: usize = 100;
+
+/// A visited location, with what is needed to restore the view when coming
+/// back to it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    pub selected: Option<String>,
+    pub scroll: f64,
+}
+
+impl HistoryEntry {
+    pub fn new(path: &Path) -> Self {
+        HistoryEntry {
+            path: path.to_owned(),
+            selected: None,
+            scroll: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct History {
+    back: VecDeque<HistoryEntry>,
+    current: HistoryEntry,
+    forward: Vec<HistoryEntry>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(path: &Path, limit: usize) -> Self {
+        History {
+            back: VecDeque::new(),
+            current: HistoryEntry::new(path),
+            forward: vec![],
+            limit,
+        }
+    }
+
+    pub fn current(&self) -> &HistoryEntry {
+        &self.current
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+
+    /// Moving to a new location drops everything that was ahead of the
+    /// current one
+    pub fn visit(&mut self, path: &Path) {
+        if self.current.path == path {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.current, HistoryEntry::new(path));
+        self.back.push_back(previous);
+        if self.back.len() > self.limit {
+            self.back.pop_front();
+        }
+        self.forward.clear();
+    }
+
+    pub fn go_back(&mut self) -> Option<&HistoryEntry> {
+        let previous = self.back.pop_back()?;
+        self.forward
+            .push(std::mem::replace(&mut self.current, previous));
+        Some(&self.current)
+    }
+
+    pub fn go_forward(&mut self) -> Option<&HistoryEntry> {
+        let next = self.forward.pop()?;
+        self.back
+            .push_back(std::mem::replace(&mut self.current, next));
+        Some(&self.current)
+    }
+
+    /// Where `go_back` would lead
+    pub fn peek_back(&self) -> Option<&HistoryEntry> {
+        self.back.back()
+    }
+
+    /// Where `go_forward` would lead
+    pub fn peek_forward(&self) -> Option<&HistoryEntry> {
+        self.forward.last()
+    }
+
+    /// Forgets where `go_back` would lead, eg. when it has been deleted
+    pub fn drop_back(&mut self) {
+        self.back.pop_back();
+    }
+
+    /// Forgets where `go_forward` would lead
+    pub fn drop_forward(&mut self) {
+        self.forward.pop();
+    }
+
+    /// Remembers the selection and scroll position of the current location
+    pub fn save_view(&mut self, selected: Option<String>, scroll: f64) {
+        self.current.selected = selected;
+        self.current.scroll = scroll;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use std::path::Path;
+
+    #[test]
+    fn back_and_forward_restore_locations() {
+        let mut history = History::new(Path::new("/a"), 10);
+        history.visit(Path::new("/a/b"));
+        history.visit(Path::new("/a/b/c"));
+
+        assert_eq!(history.go_back().unwrap().path, Path::new("/a/b"));
+        assert_eq!(history.go_back().unwrap().path, Path::new("/a"));
+        assert!(history.go_back().is_none());
+        assert_eq!(history.go_forward().unwrap().path, Path::new("/a/b"));
+        assert_eq!(history.go_forward().unwrap().path, Path::new("/a/b/c"));
+        assert!(history.go_forward().is_none());
+    }
+
+    #[test]
+    fn visiting_clears_forward() {
+        let mut history = History::new(Path::new("/a"), 10);
+        history.visit(Path::new("/b"));
+        history.go_back();
+        history.visit(Path::new("/c"));
+
+        assert!(!history.can_go_forward());
+        assert_eq!(history.go_back().unwrap().path, Path::new("/a"));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = History::new(Path::new("/0"), 3);
+        for i in 1..10 {
+            history.visit(Path::new(&format!("/{}", i)));
+        }
+
+        let mut count = 0;
+        while history.go_back().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        assert_eq!(history.current().path, Path::new("/6"));
+    }
+
+    #[test]
+    fn view_is_restored() {
+        let mut history = History::new(Path::new("/a"), 10);
+        history.save_view(Some(String::from("file.txt")), 120.0);
+        history.visit(Path::new("/b"));
+
+        let restored = history.go_back().unwrap();
+        assert_eq!(restored.selected, Some(String::from("file.txt")));
+        assert_eq!(restored.scroll, 120.0);
+    }
+
+    #[test]
+    fn dropped_entries_are_skipped() {
+        let mut history = History::new(Path::new("/a"), 10);
+        history.visit(Path::new("/b"));
+        history.visit(Path::new("/c"));
+        history.drop_back();
+
+        assert_eq!(history.peek_back().unwrap().path, Path::new("/a"));
+        assert_eq!(history.go_back().unwrap().path, Path::new("/a"));
+        assert_eq!(history.peek_forward().unwrap().path, Path::new("/c"));
+        history.drop_forward();
+        assert!(!history.can_go_forward());
+    }
+}
@@ -0,0 +1,166 @@
+use super::CurrentDirError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+pub type JobId = u32;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct JobProgress {
+    pub job_id: JobId,
+    /// Bytes processed so far, what they are depends on the job
+    pub done: u64,
+    pub total: u64,
+    pub current: Option<PathBuf>,
+}
+
+/// Sent once when a job ends, `error` is `None` on success
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct JobFinished {
+    pub job_id: JobId,
+    pub path: Option<PathBuf>,
+    /// Entries that were left out, for example unsafe paths in archives
+    pub skipped: Vec<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Handle that a long running task checks for cancellation
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: JobId,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Job {
+    /// For work that runs to the end without being registered anywhere
+    pub fn detached() -> Self {
+        Job {
+            id: JobId::MAX,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns `Cancelled` once the job has been cancelled
+    pub fn check(&self) -> Result<(), CurrentDirError> {
+        match self.is_cancelled() {
+            true => Err(CurrentDirError::Cancelled),
+            false => Ok(()),
+        }
+    }
+
+    pub fn progress(&self, done: u64, total: u64, current: Option<&Path>) -> JobProgress {
+        JobProgress {
+            job_id: self.id,
+            done,
+            total,
+            current: current.map(Path::to_owned),
+        }
+    }
+
+    /// Cancellation shows up as an io error in the middle of reading, so the
+    /// error is replaced when the job has been cancelled
+    pub fn io_error(&self, err: io::Error, wrap: fn(String) -> CurrentDirError) -> CurrentDirError {
+        match self.is_cancelled() {
+            true => CurrentDirError::Cancelled,
+            false => wrap(err.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct JobRegistry {
+    running: HashMap<JobId, Job>,
+    next_id: JobId,
+}
+
+impl JobRegistry {
+    pub fn start(&mut self) -> Job {
+        let job = Job {
+            id: self.next_id,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        self.next_id += 1;
+        self.running.insert(job.id, job.clone());
+        job
+    }
+
+    pub fn cancel(&mut self, id: JobId) -> Result<(), CurrentDirError> {
+        let job = self
+            .running
+            .remove(&id)
+            .ok_or(CurrentDirError::JobNotFound { id })?;
+        job.cancelled.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn finish(&mut self, id: JobId) {
+        self.running.remove(&id);
+    }
+}
+
+/// Counts the bytes read through it and fails the read once the job has been
+/// cancelled, so that cancelling doesn't wait for a big file to finish
+pub struct JobReader<R> {
+    inner: R,
+    job: Job,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> JobReader<R> {
+    pub fn new(inner: R, job: &Job, read: &Arc<AtomicU64>) -> Self {
+        JobReader {
+            inner,
+            job: job.clone(),
+            read: Arc::clone(read),
+        }
+    }
+}
+
+impl<R: Read> Read for JobReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.job.is_cancelled() {
+            return Err(io::Error::other("cancelled"));
+        }
+        let read = self.inner.read(buf)?;
+        self.read.fetch_add(read as u64, Ordering::SeqCst);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobReader, JobRegistry};
+    use crate::CurrentDirError;
+    use std::{
+        io::{self, Read},
+        sync::{atomic::AtomicU64, Arc},
+    };
+
+    #[test]
+    fn cancelled_job_stops_reading() {
+        let mut registry = JobRegistry::default();
+        let job = registry.start();
+        let read = Arc::new(AtomicU64::new(0));
+        let mut reader = JobReader::new(io::repeat(0).take(10), &job, &read);
+
+        let mut buffer = [0; 4];
+        reader.read_exact(&mut buffer).unwrap();
+        registry.cancel(job.id).unwrap();
+
+        assert!(reader.read_exact(&mut buffer).is_err());
+        assert_eq!(read.load(std::sync::atomic::Ordering::SeqCst), 4);
+        assert!(matches!(job.check(), Err(CurrentDirError::Cancelled)));
+        assert!(registry.cancel(job.id).is_err());
+    }
+}
@@ -1,9 +1,40 @@
+pub mod archive;
+pub mod bookmarks;
+pub mod checksums;
+pub mod comparison;
+pub mod completion;
+pub mod compression;
+pub mod credentials;
+pub mod database;
+pub mod diskusage;
+pub mod duplicates;
 pub mod filecache;
+pub mod frecency;
+pub mod hashing;
+pub mod history;
+pub mod jobs;
+pub mod listing;
+pub mod memfs;
+pub mod mime;
+pub mod permissions;
+pub mod platform;
+pub mod preview;
+pub mod properties;
+pub mod sftp;
+pub mod snapshot;
+pub mod sorting;
+pub mod tabs;
+pub mod thumbnails;
+pub mod uri;
+pub mod users;
+pub mod vfs;
+pub mod volumes;
+pub mod watcher;
+pub mod webdav;
 
 use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs,
     path::{Path, PathBuf},
     sync::Arc,
 };
@@ -12,6 +43,13 @@ use std::{
 pub struct CurrentDir {
     path: PathBuf,
     file_cache: Arc<filecache::FileCache>,
+    /// Used for every path that isn't remote
+    backend: Arc<dyn vfs::VfsBackend>,
+    connections: vfs::Connections,
+    history: history::History,
+    snapshot: Option<snapshot::Snapshot>,
+    watcher: Option<Box<dyn vfs::VfsWatcher>>,
+    archive_cache: archive::ArchiveCache,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +64,10 @@ pub struct FileData {
     name: String,
     path: PathBuf,
     filetype: FileType,
+    mime: Option<String>,
+    size: Option<u64>,
+    /// Seconds since the unix epoch
+    modified: Option<i64>,
 }
 
 impl FileData {
@@ -34,6 +76,9 @@ impl FileData {
             name: name.to_owned(),
             path: path.to_owned(),
             filetype,
+            mime: None,
+            size: None,
+            modified: None,
         }
     }
 }
@@ -66,6 +111,9 @@ impl TryFrom<&filecache::CachedFile> for FileData {
             name: value.name.to_owned(),
             path: Path::new(&value.path).to_path_buf(),
             filetype: FileType::try_from(value.filetype.as_str())?,
+            mime: None,
+            size: None,
+            modified: None,
         })
     }
 }
@@ -76,6 +124,9 @@ impl From<walkdir::DirEntry> for FileData {
             name: value.file_name().to_string_lossy().to_string(),
             path: value.path().to_path_buf(),
             filetype: FileType::from(value.file_type()),
+            mime: None,
+            size: None,
+            modified: None,
         }
     }
 }
@@ -137,14 +188,203 @@ pub enum CurrentDirError {
     },
     CannotWriteToFile,
     SearchedFileNotFound,
+    #[display(fmt = "Cannot read metadata of \"{}\"", path)]
+    CannotReadMetadata {
+        path: String,
+    },
+    #[display(fmt = "Permission denied: \"{}\"", path)]
+    PermissionDenied {
+        path: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotChangePermissions {
+        reason: String,
+    },
+    #[display(fmt = "Invalid mode \"{}\"", mode)]
+    InvalidMode {
+        mode: String,
+    },
+    #[display(fmt = "Unknown user \"{}\"", name)]
+    UnknownUser {
+        name: String,
+    },
+    #[display(fmt = "Unknown group \"{}\"", name)]
+    UnknownGroup {
+        name: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotCreateThumbnail {
+        reason: String,
+    },
+    #[display(fmt = "\"{}\" isn't a regular file", path)]
+    NotARegularFile {
+        path: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotReadFile {
+        reason: String,
+    },
+    NoHistoryLeft,
+    #[display(fmt = "Tab {} doesn't exist", id)]
+    TabNotFound {
+        id: tabs::TabId,
+    },
+    #[display(fmt = "{}", reason)]
+    DatabaseError {
+        reason: String,
+    },
+    #[display(fmt = "Bookmark {} doesn't exist", id)]
+    BookmarkNotFound {
+        id: i64,
+    },
+    #[display(fmt = "No visited folder matches \"{}\"", query)]
+    NoJumpTarget {
+        query: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotWatch {
+        reason: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotReadArchive {
+        reason: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotExtract {
+        reason: String,
+    },
+    NotInArchive,
+    #[display(fmt = "{}", reason)]
+    CannotCompress {
+        reason: String,
+    },
+    Cancelled,
+    #[display(fmt = "Job {} isn't running", id)]
+    JobNotFound {
+        id: jobs::JobId,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotConnect {
+        reason: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotRename {
+        reason: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotCopy {
+        reason: String,
+    },
+    #[display(fmt = "\"{}\" isn't a remote url", url)]
+    NotARemoteUrl {
+        url: String,
+    },
+    #[display(fmt = "{}", reason)]
+    CannotStorePassword {
+        reason: String,
+    },
+    #[display(fmt = "Cannot tell which hash \"{}\" uses", path)]
+    UnknownManifest {
+        path: String,
+    },
+}
+
+impl From<tokio_rusqlite::Error> for CurrentDirError {
+    fn from(value: tokio_rusqlite::Error) -> Self {
+        CurrentDirError::DatabaseError {
+            reason: value.to_string(),
+        }
+    }
 }
 
 impl CurrentDir {
-    pub async fn new(path: &Path) -> Self {
-        let parsed_path = CurrentDir::parse_path_to_absolute(path).unwrap();
-        CurrentDir {
-            path: parsed_path,
-            file_cache: filecache::FileCache::new().await,
+    pub fn new(
+        path: &Path,
+        file_cache: Arc<filecache::FileCache>,
+    ) -> Result<Self, CurrentDirError> {
+        CurrentDir::with_backend(path, file_cache, Arc::new(vfs::LocalBackend::default()))
+    }
+
+    /// Browses `backend` instead of the local disk
+    pub fn with_backend(
+        path: &Path,
+        file_cache: Arc<filecache::FileCache>,
+        backend: Arc<dyn vfs::VfsBackend>,
+    ) -> Result<Self, CurrentDirError> {
+        let mut current_dir = CurrentDir {
+            history: history::History::new(path, history::HISTORY_LIMIT),
+            path: path.to_owned(),
+            file_cache,
+            backend,
+            connections: vfs::Connections::default(),
+            snapshot: None,
+            watcher: None,
+            archive_cache: archive::ArchiveCache::default(),
+        };
+        let parsed_path = current_dir.canonicalize(path)?;
+        current_dir.history = history::History::new(&parsed_path, history::HISTORY_LIMIT);
+        current_dir.path = parsed_path;
+        Ok(current_dir)
+    }
+
+    /// Remote paths are served by their server, everything else by the
+    /// backend that the `CurrentDir` was created with
+    fn backend_for(&self, path: &Path) -> Result<Arc<dyn vfs::VfsBackend>, CurrentDirError> {
+        match vfs::RemoteUrl::parse(path) {
+            Some((url, _)) => {
+                self.connections
+                    .get(&url)
+                    .map_err(|err| CurrentDirError::CannotConnect {
+                        reason: err.to_string(),
+                    })
+            }
+            None => Ok(Arc::clone(&self.backend)),
+        }
+    }
+
+    /// Archives are looked for on the backend of the `CurrentDir`
+    fn locate(&self, path: &Path) -> vfs::Location {
+        vfs::Location::resolve(self.backend.as_ref(), path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, CurrentDirError> {
+        self.backend_for(path)?
+            .canonicalize(path)
+            .map_err(|_| CurrentDirError::PathCannotBeMadeAbsolute)
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every move that should be undoable with `go_back` goes through here
+    fn visit(&mut self, path: PathBuf) {
+        self.history.visit(&path);
+        self.path = path;
+        self.follow_watcher();
+    }
+
+    /// Reports changes to the entries of the current folder. The watch moves
+    /// along when the current folder changes.
+    pub fn watch(
+        &mut self,
+        on_changes: impl Fn(Vec<watcher::FolderChange>) + Send + Sync + 'static,
+    ) -> Result<(), CurrentDirError> {
+        let watcher = self
+            .backend_for(&self.path)?
+            .watch(&self.path, Box::new(on_changes))
+            .map_err(|err| CurrentDirError::CannotWatch {
+                reason: err.to_string(),
+            })?;
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// Folders that can't be watched, for example because of the inotify
+    /// limits, are just listed without live updates
+    fn follow_watcher(&mut self) {
+        if let Some(watcher) = self.watcher.as_mut() {
+            let _ = watcher.follow(&self.path);
         }
     }
 
@@ -155,57 +395,258 @@ impl CurrentDir {
             .to_str()
             .ok_or(CurrentDirError::IsntUTF8)?
             .to_string();
-        let parent_path = self.path.parent().ok_or(CurrentDirError::AlreadyAtRoot)?;
-        self.path = parent_path.to_owned();
+        let parent_path = vfs::parent(&self.path).ok_or(CurrentDirError::AlreadyAtRoot)?;
+        self.visit(parent_path);
         Ok(old_path)
     }
 
     pub fn move_to_dir(&mut self, path: &Path, to_parent: bool) -> Result<(), CurrentDirError> {
-        let parsed = CurrentDir::parse_path_to_absolute(path)?;
-        if !parsed.is_dir() && !to_parent {
+        if let vfs::Location::Archive { archive, inner } = self.locate(path) {
+            return self.move_into_archive(&archive, &inner, to_parent);
+        }
+
+        let parsed = self.canonicalize(path)?;
+        let is_dir = self
+            .backend_for(&parsed)?
+            .stat(&parsed)
+            .is_ok_and(|metadata| metadata.filetype == FileType::Folder);
+        if !is_dir && !to_parent {
             return Err(CurrentDirError::CannotMoveToFile {
                 file_name: path.to_str().unwrap().to_string(),
             });
         }
-        self.path = parsed;
 
-        if to_parent {
-            self.move_to_parent_dir()?;
+        let target = if to_parent {
+            vfs::parent(&parsed).ok_or(CurrentDirError::AlreadyAtRoot)?
+        } else {
+            parsed
+        };
+        self.visit(target);
+
+        Ok(())
+    }
+
+    /// Archives are entered like folders. Moving to the parent of the root of
+    /// an archive leaves the archive.
+    fn move_into_archive(
+        &mut self,
+        archive: &Path,
+        inner: &Path,
+        to_parent: bool,
+    ) -> Result<(), CurrentDirError> {
+        let archive = self.canonicalize(archive)?;
+        let inner = match to_parent {
+            true => match inner.parent() {
+                Some(parent) => parent.to_owned(),
+                None => {
+                    let parent = archive.parent().ok_or(CurrentDirError::AlreadyAtRoot)?;
+                    self.visit(parent.to_owned());
+                    return Ok(());
+                }
+            },
+            false => inner.to_owned(),
+        };
+        if !self.archive_cache.get(&archive)?.is_dir(&inner) {
+            return Err(CurrentDirError::CannotMoveToFile {
+                file_name: archive.join(inner).to_string_lossy().to_string(),
+            });
         }
 
+        self.visit(archive.join(inner).components().collect());
         Ok(())
     }
 
-    pub fn get_siblings(&self) -> Result<Vec<FileData>, CurrentDirError> {
-        let mut siblings = vec![];
-        for entry in fs::read_dir(&self.path)
-            .map_err(|err| CurrentDirError::CannotReadDir {
+    /// Whether `path` can still be moved to. Archives count as folders.
+    fn is_folder(&self, path: &Path) -> bool {
+        match self.locate(path) {
+            vfs::Location::Archive { archive, inner } => self
+                .archive_cache
+                .get(&archive)
+                .is_ok_and(|index| index.is_dir(&inner)),
+            _ => self
+                .backend_for(path)
+                .ok()
+                .and_then(|backend| backend.stat(path).ok())
+                .is_some_and(|metadata| metadata.filetype == FileType::Folder),
+        }
+    }
+
+    /// Returns the entry moved to, so that its selection and scroll position
+    /// can be restored. Folders deleted since they were visited are skipped
+    /// and forgotten.
+    pub fn go_back(&mut self) -> Result<history::HistoryEntry, CurrentDirError> {
+        while let Some(previous) = self.history.peek_back() {
+            if self.is_folder(&previous.path) {
+                break;
+            }
+            self.history.drop_back();
+        }
+        let entry = self
+            .history
+            .go_back()
+            .ok_or(CurrentDirError::NoHistoryLeft)?
+            .clone();
+        self.path = entry.path.clone();
+        self.follow_watcher();
+        Ok(entry)
+    }
+
+    pub fn go_forward(&mut self) -> Result<history::HistoryEntry, CurrentDirError> {
+        while let Some(next) = self.history.peek_forward() {
+            if self.is_folder(&next.path) {
+                break;
+            }
+            self.history.drop_forward();
+        }
+        let entry = self
+            .history
+            .go_forward()
+            .ok_or(CurrentDirError::NoHistoryLeft)?
+            .clone();
+        self.path = entry.path.clone();
+        self.follow_watcher();
+        Ok(entry)
+    }
+
+    pub fn get_history(&self) -> &history::History {
+        &self.history
+    }
+
+    pub fn save_view_state(&mut self, selected: Option<String>, scroll: f64) {
+        self.history.save_view(selected, scroll);
+    }
+
+    /// Detecting the mime type may read the start of the file, so it can be
+    /// left out and filled in later for the entries that are actually shown
+    fn read_entries(
+        &self,
+        options: &listing::ListingOptions,
+        with_mime: bool,
+    ) -> Result<Vec<FileData>, CurrentDirError> {
+        if let vfs::Location::Archive { archive, inner } = self.locate(&self.path) {
+            let ignore = listing::GitIgnore::default();
+            return Ok(self
+                .archive_cache
+                .get(&archive)?
+                .children(&inner)
+                .into_iter()
+                .map(|entry| entry.to_file_data(&archive))
+                .filter(|file| {
+                    options.shows(
+                        &file.name,
+                        &file.path,
+                        file.filetype == FileType::Folder,
+                        &ignore,
+                    )
+                })
+                .collect());
+        }
+
+        let backend = self.backend_for(&self.path)?;
+        let ignore = match options.show_ignored {
+            true => listing::GitIgnore::default(),
+            false => listing::GitIgnore::load(backend.as_ref(), &self.path),
+        };
+        let entries = backend.list(&self.path).map_err(|err| {
+            // A dropped connection is opened again on the next try
+            if let Some((url, _)) = vfs::RemoteUrl::parse(&self.path) {
+                self.connections.forget(&url);
+            }
+            CurrentDirError::CannotReadDir {
                 dir_name: err.to_string(),
-            })?
-            .filter_map(|v| v.ok())
-        {
+            }
+        })?;
+        let mut siblings = vec![];
+        for entry in entries {
             let name = entry
+                .path
                 .file_name()
-                .to_str()
+                .and_then(|name| name.to_str())
                 .ok_or(CurrentDirError::IsntUTF8)?
                 .to_owned();
-            let path = entry.path();
-            let filetype = FileType::from(
-                entry
-                    .file_type()
-                    .map_err(|_| CurrentDirError::CannotGetFileType)?,
-            );
+            let filetype = entry.metadata.filetype;
+            if !options.shows(&name, &entry.path, filetype == FileType::Folder, &ignore) {
+                continue;
+            }
 
             siblings.push(FileData {
+                mime: with_mime.then(|| backend.mime(&entry.path, &entry.metadata).to_owned()),
+                size: Some(entry.metadata.size),
+                modified: Some(entry.metadata.modified),
                 name,
-                path,
+                path: entry.path,
                 filetype,
             })
         }
-        siblings.sort_unstable();
         Ok(siblings)
     }
 
+    pub fn get_siblings(
+        &self,
+        sort_mode: &sorting::SortMode,
+        options: &listing::ListingOptions,
+    ) -> Result<Vec<FileData>, CurrentDirError> {
+        let mut siblings = self.read_entries(options, true)?;
+        sort_mode.sort(&mut siblings);
+        Ok(siblings)
+    }
+
+    /// Makes the next window re-read the folder
+    pub fn invalidate_snapshot(&mut self) {
+        self.snapshot = None;
+    }
+
+    /// Returns `limit` entries starting from `offset` in sorted order. The
+    /// folder is read once into a snapshot that later windows are taken from.
+    pub fn get_folder_window(
+        &mut self,
+        sort_mode: &sorting::SortMode,
+        options: &listing::ListingOptions,
+        offset: usize,
+        limit: usize,
+    ) -> Result<snapshot::FolderWindow, CurrentDirError> {
+        if let Some(watcher) = self.watcher.as_ref() {
+            if watcher.take_changed() {
+                self.invalidate_snapshot();
+            }
+        }
+        let backend = self.backend_for(&self.path)?;
+        let mut snapshot = match self.snapshot.take() {
+            Some(snapshot)
+                if snapshot.is_valid_for(backend.as_ref(), &self.path, sort_mode, options) =>
+            {
+                snapshot
+            }
+            _ => {
+                // Sorting only looks at the extensions, so the types are
+                // detected for the rows in the window below
+                let entries = self.read_entries(options, false)?;
+                snapshot::Snapshot::new(backend.as_ref(), &self.path, sort_mode, options, entries)
+            }
+        };
+
+        let mut files = vec![];
+        for file in snapshot.window_mut(offset, limit) {
+            if file.mime.is_none() {
+                file.mime = backend
+                    .stat(&file.path)
+                    .ok()
+                    .map(|metadata| backend.mime(&file.path, &metadata).to_owned());
+            }
+            files.push(file.clone());
+        }
+        let total = snapshot.total();
+        self.snapshot = Some(snapshot);
+
+        Ok(snapshot::FolderWindow {
+            name: self.get_current_folder_name()?.to_owned(),
+            total,
+            offset,
+            files,
+            is_at_root: self.current_dir_is_root(),
+        })
+    }
+
     pub fn get_current_folder_name(&self) -> Result<&str, CurrentDirError> {
         self.path
             .file_name()
@@ -214,8 +655,12 @@ impl CurrentDir {
             .ok_or(CurrentDirError::IsntUTF8)
     }
 
-    pub fn get_folder_data(&self) -> Result<FolderData, CurrentDirError> {
-        let siblings = self.get_siblings()?;
+    pub fn get_folder_data(
+        &self,
+        sort_mode: &sorting::SortMode,
+        options: &listing::ListingOptions,
+    ) -> Result<FolderData, CurrentDirError> {
+        let siblings = self.get_siblings(sort_mode, options)?;
         let name = self.get_current_folder_name()?.to_owned();
 
         Ok(FolderData {
@@ -225,12 +670,8 @@ impl CurrentDir {
         })
     }
 
-    pub fn parse_path_to_absolute(path: &Path) -> Result<PathBuf, CurrentDirError> {
-        fs::canonicalize(path).map_err(|_| CurrentDirError::PathCannotBeMadeAbsolute)
-    }
-
     pub fn current_dir_is_root(&self) -> bool {
-        self.path.parent().is_none()
+        vfs::parent(&self.path).is_none()
     }
 
     pub async fn search_files(
@@ -241,10 +682,18 @@ impl CurrentDir {
         search_links: bool,
         exact: bool,
     ) -> Result<Vec<FileData>, CurrentDirError> {
-        let mut data = self
-            .file_cache
-            .find_file(name, exact)
-            .await
+        // Remote servers aren't in the file cache, so they are walked instead
+        let found = match vfs::RemoteUrl::parse(&self.path) {
+            Some(_) => Some(vfs::find(
+                self.backend_for(&self.path)?.as_ref(),
+                &self.path,
+                &name,
+                exact,
+            ))
+            .filter(|found| !found.is_empty()),
+            None => self.file_cache.find_file(name, exact).await,
+        };
+        let mut data = found
             .ok_or(CurrentDirError::SearchedFileNotFound)?
             .into_iter()
             .filter(|file| match file.filetype {
@@ -267,51 +716,190 @@ impl CurrentDir {
         let mut path_to_file = self.path.clone();
         path_to_file.push(Path::new(&filename));
 
-        match filetype_parsed {
-            FileType::Folder => tokio::fs::create_dir(path_to_file).await.map_err(|err| {
-                CurrentDirError::CannotCreateFile {
-                    reason: err.to_string(),
-                }
-            }),
-            FileType::Link => Err(CurrentDirError::CannotCreateFile {
+        if filetype_parsed == FileType::Link {
+            return Err(CurrentDirError::CannotCreateFile {
                 reason: "Links aren't supported yet!".to_string(),
-            }),
-            FileType::File => {
-                tokio::fs::File::create(path_to_file).await.map_err(|err| {
-                    CurrentDirError::CannotCreateFile {
-                        reason: err.to_string(),
-                    }
-                })?;
-                Ok(())
-            }
+            });
         }
+        self.backend_for(&self.path)?
+            .create(&path_to_file, filetype_parsed)
+            .map_err(|err| CurrentDirError::CannotCreateFile {
+                reason: err.to_string(),
+            })
     }
 
+    /// The backend looks up what the file is, `filetype` only has to be valid
     pub async fn delete_file(
         &self,
         filename: String,
         filetype: String,
     ) -> Result<(), CurrentDirError> {
-        let filetype_parsed = FileType::try_from(filetype.as_str())?;
+        FileType::try_from(filetype.as_str())?;
         let mut path_to_file = self.path.clone();
         path_to_file.push(Path::new(&filename));
 
-        match filetype_parsed {
-            FileType::Folder => tokio::fs::remove_dir(path_to_file).await,
-            _ => tokio::fs::remove_file(path_to_file).await,
+        self.backend_for(&self.path)?
+            .remove(&path_to_file)
+            .map_err(|err| CurrentDirError::CannotDeleteFile {
+                reason: err.to_string(),
+            })
+    }
+
+    /// Renames an entry of the current folder. An entry that already has the
+    /// new name is never replaced. Returns the new path.
+    pub fn rename_file(
+        &self,
+        filename: String,
+        new_name: String,
+    ) -> Result<PathBuf, CurrentDirError> {
+        if new_name.is_empty() || new_name.contains('/') || new_name == "." || new_name == ".." {
+            return Err(CurrentDirError::CannotRename {
+                reason: format!("\"{}\" isn't a valid name", new_name),
+            });
         }
-        .map_err(|err| CurrentDirError::CannotDeleteFile {
-            reason: err.to_string(),
-        })
+        let backend = self.backend_for(&self.path)?;
+        let from = self.path.join(filename);
+        let to = self.path.join(new_name);
+        if backend.stat(&to).is_ok() {
+            return Err(CurrentDirError::CannotRename {
+                reason: format!("{} already exists", to.display()),
+            });
+        }
+        backend
+            .rename(&from, &to)
+            .map_err(|err| CurrentDirError::CannotRename {
+                reason: err.to_string(),
+            })?;
+        Ok(to)
+    }
+
+    /// Copies entries of the current folder into `destination`, which can be
+    /// on another server or on the local disk. Existing entries are never
+    /// replaced.
+    pub fn copy_entries(
+        &self,
+        filenames: Vec<String>,
+        destination: &Path,
+    ) -> Result<vfs::Copied, CurrentDirError> {
+        let cannot_copy = |reason: String| CurrentDirError::CannotCopy { reason };
+        let backend = self.backend_for(&self.path)?;
+        let destination_backend = self.backend_for(destination)?;
+        match destination_backend.stat(destination) {
+            Ok(metadata) if metadata.filetype == FileType::Folder => {}
+            _ => {
+                return Err(cannot_copy(format!(
+                    "{} isn't a folder",
+                    destination.display()
+                )))
+            }
+        }
+
+        let mut copied = vfs::Copied::default();
+        for filename in filenames {
+            let target = destination.join(&filename);
+            if destination_backend.stat(&target).is_ok() {
+                return Err(cannot_copy(format!("{} already exists", target.display())));
+            }
+            let source = self.path.join(&filename);
+            // Servers can copy their own files without downloading them
+            let copied_inside = Arc::ptr_eq(&backend, &destination_backend)
+                && backend.copy_inside(&source, &target).is_ok();
+            let skipped = match copied_inside {
+                true => vec![],
+                false => vfs::copy(
+                    backend.as_ref(),
+                    &source,
+                    destination_backend.as_ref(),
+                    &target,
+                )
+                .map_err(|err| cannot_copy(err.to_string()))?,
+            };
+            copied.paths.push(target);
+            copied.skipped.extend(skipped);
+        }
+        Ok(copied)
+    }
+
+    /// Copies entries of the current folder out of the archive it is in.
+    /// Returns the paths of the extracted copies.
+    pub fn extract_entries(
+        &self,
+        filenames: Vec<String>,
+        destination: &Path,
+    ) -> Result<Vec<PathBuf>, CurrentDirError> {
+        let vfs::Location::Archive { archive, inner } = self.locate(&self.path) else {
+            return Err(CurrentDirError::NotInArchive);
+        };
+        filenames
+            .iter()
+            .map(|filename| {
+                archive::extract(&archive, &inner.join(filename), destination)
+                    .map(|extracted| extracted.path)
+            })
+            .collect()
+    }
+
+    fn paths_in_current_dir(&self, filenames: &[String]) -> Vec<PathBuf> {
+        filenames
+            .iter()
+            .map(|filename| self.path.join(filename))
+            .collect()
+    }
+
+    /// `mode` can be octal (`644`) or symbolic (`u+x,go-w`)
+    pub fn change_mode(
+        &self,
+        filenames: Vec<String>,
+        mode: String,
+        recursive: bool,
+        dry_run: bool,
+    ) -> Result<Vec<permissions::PermissionChange>, CurrentDirError> {
+        let mode = permissions::Mode::try_from(mode.as_str())?;
+        let paths = self.paths_in_current_dir(&filenames);
+
+        Ok(permissions::change_mode(&paths, &mode, recursive, dry_run))
+    }
+
+    /// `owner` and `group` can be given as names or as numeric ids. The ones
+    /// left as `None` are kept unchanged.
+    pub fn change_owner(
+        &self,
+        filenames: Vec<String>,
+        owner: Option<String>,
+        group: Option<String>,
+        recursive: bool,
+        dry_run: bool,
+    ) -> Result<Vec<permissions::PermissionChange>, CurrentDirError> {
+        let uid = owner
+            .map(|name| {
+                name.parse()
+                    .ok()
+                    .or_else(|| users::user_id(&name))
+                    .ok_or(CurrentDirError::UnknownUser { name })
+            })
+            .transpose()?;
+        let gid = group
+            .map(|name| {
+                name.parse()
+                    .ok()
+                    .or_else(|| users::group_id(&name))
+                    .ok_or(CurrentDirError::UnknownGroup { name })
+            })
+            .transpose()?;
+        let paths = self.paths_in_current_dir(&filenames);
+
+        Ok(permissions::change_owner(
+            &paths, uid, gid, recursive, dry_run,
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::filecache::CachedFile;
+    use crate::filecache::{CachedFile, FileCache};
 
-    use super::{FileData, FileType};
-    use std::path::Path;
+    use super::{CurrentDir, FileData, FileType};
+    use std::{fs, path::Path, sync::Arc};
 
     #[test]
     fn filedata_is_equal() {
@@ -319,6 +907,9 @@ mod tests {
             name: String::from("asd"),
             path: Path::new("/").to_owned(),
             filetype: FileType::File,
+            mime: None,
+            size: None,
+            modified: None,
         };
         assert_eq!(some, some);
     }
@@ -329,16 +920,25 @@ mod tests {
             name: String::from("asd"),
             path: Path::new("/").to_owned(),
             filetype: FileType::File,
+            mime: None,
+            size: None,
+            modified: None,
         };
         let different_filetype = FileData {
             name: String::from("asd"),
             path: Path::new("/").to_owned(),
             filetype: FileType::Folder,
+            mime: None,
+            size: None,
+            modified: None,
         };
         let different_name = FileData {
             name: String::from("asdf"),
             path: Path::new("/").to_owned(),
             filetype: FileType::File,
+            mime: None,
+            size: None,
+            modified: None,
         };
         assert_ne!(some, different_filetype);
         assert_ne!(some, different_name);
@@ -411,4 +1011,23 @@ mod tests {
             FileData::try_from(&test).unwrap()
         )
     }
+
+    #[tokio::test]
+    async fn deleted_folders_are_skipped_in_history() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(temp.path()).unwrap();
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let mut current_dir = CurrentDir::new(&root, cache).unwrap();
+        current_dir.move_to_dir(&root.join("a"), false).unwrap();
+        current_dir.move_to_dir(&root.join("a/b"), false).unwrap();
+        current_dir.move_to_dir(&root, false).unwrap();
+        fs::remove_dir(root.join("a/b")).unwrap();
+
+        assert_eq!(current_dir.go_back().unwrap().path, root.join("a"));
+        assert_eq!(current_dir.go_back().unwrap().path, root);
+        assert_eq!(current_dir.go_forward().unwrap().path, root.join("a"));
+        assert_eq!(current_dir.go_forward().unwrap().path, root);
+        assert!(current_dir.go_forward().is_err());
+    }
 }
@@ -0,0 +1,272 @@
+use super::vfs::VfsBackend;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ListingOptions {
+    pub show_hidden: bool,
+    /// Files ending with `~`
+    pub show_backups: bool,
+    /// Entries matched by a `.gitignore` of the repository the folder is in
+    pub show_ignored: bool,
+    /// Glob if it contains `*`, `?` or `[`, otherwise a substring. Both ignore
+    /// case.
+    pub filter: Option<String>,
+}
+
+impl Default for ListingOptions {
+    fn default() -> Self {
+        ListingOptions {
+            show_hidden: false,
+            show_backups: false,
+            show_ignored: true,
+            filter: None,
+        }
+    }
+}
+
+/// `*` and `?` don't match slashes, `**` matches anything including them
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) if rest.first() == Some(&'*') => {
+                let rest = &rest[1..];
+                let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+                (0..=text.len()).any(|skip| matches(rest, &text[skip..]))
+            }
+            Some(('*', rest)) => {
+                let segment = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+                (0..=segment).any(|skip| matches(rest, &text[skip..]))
+            }
+            Some(('?', rest)) => {
+                matches!(text.first(), Some(&c) if c != '/') && matches(rest, &text[1..])
+            }
+            Some(('[', rest)) => {
+                let Some(end) = rest.iter().skip(1).position(|&c| c == ']') else {
+                    return text.first() == Some(&'[') && matches(rest, &text[1..]);
+                };
+                let (class, rest) = (&rest[..end + 1], &rest[end + 2..]);
+                let Some(&c) = text.first() else {
+                    return false;
+                };
+                let (negated, class) = match class.split_first() {
+                    Some(('!' | '^', class)) => (true, class),
+                    _ => (false, class),
+                };
+                let mut found = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        found |= class[i] <= c && c <= class[i + 2];
+                        i += 3;
+                    } else {
+                        found |= class[i] == c;
+                        i += 1;
+                    }
+                }
+                found != negated && matches(rest, &text[1..])
+            }
+            Some((&p, rest)) => text.first() == Some(&p) && matches(rest, &text[1..]),
+        }
+    }
+
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    matches(&pattern, &text)
+}
+
+pub fn name_matches(filter: &str, name: &str) -> bool {
+    let filter = filter.to_lowercase();
+    let name = name.to_lowercase();
+    if filter.contains(['*', '?', '[']) {
+        glob_match(&filter, &name)
+    } else {
+        name.contains(&filter)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IgnoreRule {
+    pattern: String,
+    negated: bool,
+    directory_only: bool,
+    /// Patterns with a slash are matched against the path relative to the
+    /// `.gitignore`, others against the name only
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (directory_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        Some(IgnoreRule {
+            pattern: line.trim_start_matches('/').to_owned(),
+            negated,
+            directory_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative: &str, name: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        match self.anchored {
+            true => glob_match(&self.pattern, relative),
+            false => glob_match(&self.pattern, name),
+        }
+    }
+}
+
+/// The `.gitignore` files from the repository root down to a folder. Only the
+/// entries themselves are matched, so everything inside an ignored folder is
+/// shown once the folder has been opened.
+#[derive(Debug, Default)]
+pub struct GitIgnore {
+    /// Outermost first, later rules override earlier ones
+    files: Vec<(PathBuf, Vec<IgnoreRule>)>,
+}
+
+impl GitIgnore {
+    /// Empty if the folder isn't inside a git repository. The files are
+    /// read through `backend`, which `folder` belongs to.
+    pub fn load(backend: &dyn VfsBackend, folder: &Path) -> Self {
+        let read = |path: &Path| -> std::io::Result<String> {
+            let mut content = String::new();
+            backend.open(path)?.read_to_string(&mut content)?;
+            Ok(content)
+        };
+        let mut files = vec![];
+        for ancestor in folder.ancestors() {
+            if let Ok(content) = read(&ancestor.join(".gitignore")) {
+                files.push((
+                    ancestor.to_path_buf(),
+                    content.lines().filter_map(IgnoreRule::parse).collect(),
+                ));
+            }
+            if backend.stat(&ancestor.join(".git")).is_ok() {
+                files.reverse();
+                return GitIgnore { files };
+            }
+        }
+        GitIgnore::default()
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+            return false;
+        };
+        let mut ignored = false;
+        for (base, rules) in self.files.iter() {
+            let Ok(relative) = path.strip_prefix(base) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy();
+            for rule in rules {
+                if rule.matches(&relative, &name, is_dir) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+impl ListingOptions {
+    /// `ignore` is only consulted when ignored entries are hidden
+    pub fn shows(&self, name: &str, path: &Path, is_dir: bool, ignore: &GitIgnore) -> bool {
+        (self.show_hidden || !name.starts_with('.'))
+            && (self.show_backups || !name.ends_with('~'))
+            && match self.filter.as_deref() {
+                Some(filter) => name_matches(filter, name),
+                None => true,
+            }
+            && (self.show_ignored || !ignore.is_ignored(path, is_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, name_matches, GitIgnore, ListingOptions};
+    use crate::{
+        memfs::MemoryBackend,
+        vfs::{LocalBackend, VfsBackend},
+        FileType,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn globs_are_matched() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("**/*.rs", "src/main.rs"));
+        assert!(glob_match("**/*.rs", "main.rs"));
+        assert!(glob_match("file?.[ch]", "file1.c"));
+        assert!(!glob_match("file?.[!ch]", "file1.c"));
+        assert!(glob_match("[a-c]*", "beta"));
+        assert!(!glob_match("a?", "a"));
+    }
+
+    #[test]
+    fn filter_is_a_glob_or_substring() {
+        assert!(name_matches("REPORT", "annual_report.pdf"));
+        assert!(name_matches("*.PDF", "annual_report.pdf"));
+        assert!(!name_matches("*.txt", "annual_report.pdf"));
+    }
+
+    #[test]
+    fn gitignore_rules_are_applied() {
+        let backend = MemoryBackend::new();
+        let root = Path::new("/repo");
+        for folder in ["/repo", "/repo/.git", "/repo/src"] {
+            backend.create(Path::new(folder), FileType::Folder).unwrap();
+        }
+        backend
+            .write(
+                &root.join(".gitignore"),
+                b"# build output\n/target/\n*.log\n",
+            )
+            .unwrap();
+        backend
+            .write(&root.join("src").join(".gitignore"), b"!keep.log\n")
+            .unwrap();
+
+        let ignore = GitIgnore::load(&backend, &root.join("src"));
+
+        assert!(ignore.is_ignored(&root.join("target"), true));
+        assert!(!ignore.is_ignored(&root.join("target"), false));
+        assert!(!ignore.is_ignored(&root.join("src").join("target"), true));
+        assert!(ignore.is_ignored(&root.join("src").join("debug.log"), false));
+        assert!(!ignore.is_ignored(&root.join("src").join("keep.log"), false));
+        assert!(GitIgnore::load(&LocalBackend::default(), Path::new("/"))
+            .files
+            .is_empty());
+    }
+
+    #[test]
+    fn hidden_and_backup_files_are_hidden_by_default() {
+        let options = ListingOptions::default();
+        let ignore = GitIgnore::default();
+        let shows = |name: &str| options.shows(name, Path::new(name), false, &ignore);
+
+        assert!(shows("notes.txt"));
+        assert!(!shows(".bashrc"));
+        assert!(!shows("notes.txt~"));
+    }
+}
@@ -1,99 +1,893 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use mielikki::FileData;
+use mielikki::archive;
+use mielikki::bookmarks::{self, Bookmark};
+use mielikki::checksums;
+use mielikki::comparison::{self, CompareMode};
+use mielikki::compression::{self, CompressFormat};
+use mielikki::credentials;
+use mielikki::database::{self, AppDatabase};
+use mielikki::diskusage::{self, FolderUsage};
+use mielikki::duplicates::{self, DuplicateOptions};
+use mielikki::filecache::{FileCache, Indexer};
+use mielikki::frecency;
+use mielikki::hashing::HashAlgorithm;
+use mielikki::history::{History, HistoryEntry};
+use mielikki::jobs::{Job, JobFinished, JobId, JobProgress, JobRegistry};
+use mielikki::listing::ListingOptions;
+use mielikki::permissions::PermissionChange;
+use mielikki::preview::{self, FilePreview};
+use mielikki::properties::{self, FileProperties};
+use mielikki::snapshot::FolderWindow;
+use mielikki::sorting::SortMode;
+use mielikki::tabs::{TabId, TabInfo, TabRegistry};
+use mielikki::thumbnails::{self, ThumbnailService};
+use mielikki::vfs::{Copied, RemoteUrl};
+use mielikki::volumes::{self, Volume};
+use mielikki::watcher::FolderChanges;
 use mielikki::{CurrentDir, CurrentDirError, FolderData};
+use mielikki::{FileData, FileType};
+use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
+use tauri::http::ResponseBuilder;
+use tauri::Manager;
 use tokio::sync::Mutex;
 
-pub struct OuterCurrentDir(pub Arc<Mutex<CurrentDir>>);
+pub struct Tabs(pub Mutex<TabRegistry>);
+
+/// Jobs are finished from blocking threads, so a std mutex is used
+#[derive(Default)]
+pub struct Jobs(pub Arc<std::sync::Mutex<JobRegistry>>);
+
+/// Taken out when the app exits
+pub struct Indexing(pub std::sync::Mutex<Option<Indexer>>);
+
+/// How long the app waits on exit for the indexer to stop
+const INDEXER_TIMEOUT: Duration = Duration::from_secs(2);
+
+impl Tabs {
+    async fn get(&self, tab_id: TabId) -> Result<Arc<Mutex<CurrentDir>>, CurrentDirError> {
+        self.0.lock().await.get(tab_id)
+    }
+}
+
+#[tauri::command]
+async fn open_tab(tabs: tauri::State<'_, Tabs>, path: String) -> Result<TabId, CurrentDirError> {
+    tabs.0.lock().await.open_tab(Path::new(&path))
+}
+
+#[tauri::command]
+async fn close_tab(tabs: tauri::State<'_, Tabs>, tab_id: TabId) -> Result<(), CurrentDirError> {
+    tabs.0.lock().await.close_tab(tab_id)
+}
+
+#[tauri::command]
+async fn list_tabs(tabs: tauri::State<'_, Tabs>) -> Result<Vec<TabInfo>, CurrentDirError> {
+    Ok(tabs.0.lock().await.list_tabs().await)
+}
 
 #[tauri::command]
 async fn get_current_folder(
-    state: tauri::State<'_, OuterCurrentDir>,
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
+    options: Option<ListingOptions>,
 ) -> Result<FolderData, CurrentDirError> {
-    let state_guard = state.0.lock().await;
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    let sort_mode = database.get_sort_mode(state_guard.get_path()).await?;
+
+    state_guard.get_folder_data(&sort_mode, &options.unwrap_or_default())
+}
 
-    state_guard.get_folder_data()
+#[tauri::command]
+async fn get_folder_window(
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
+    options: Option<ListingOptions>,
+    offset: usize,
+    limit: usize,
+) -> Result<FolderWindow, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
+    let sort_mode = database.get_sort_mode(state_guard.get_path()).await?;
+
+    state_guard.get_folder_window(&sort_mode, &options.unwrap_or_default(), offset, limit)
+}
+
+/// Changes to the folder of the tab are sent as "folder-changed" events until
+/// the tab is closed
+#[tauri::command]
+async fn watch_folder(
+    window: tauri::Window,
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+) -> Result<(), CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
+    state_guard.watch(move |changes| {
+        let _ = window.emit("folder-changed", FolderChanges { tab_id, changes });
+    })
+}
+
+#[tauri::command]
+async fn get_sort_mode(
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
+) -> Result<SortMode, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    database.get_sort_mode(state_guard.get_path()).await
+}
+
+/// The mode is remembered for the current folder of the tab
+#[tauri::command]
+async fn set_sort_mode(
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
+    sort_mode: SortMode,
+) -> Result<(), CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    database
+        .set_sort_mode(state_guard.get_path(), sort_mode)
+        .await
 }
 
 #[tauri::command]
 async fn move_to_folder(
-    state: tauri::State<'_, OuterCurrentDir>,
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
     folder_path: String,
     to_parent: bool,
 ) -> Result<(), CurrentDirError> {
-    let mut state_guard = state.0.lock().await;
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
 
-    state_guard.move_to_dir(&PathBuf::from(folder_path), to_parent)
+    state_guard.move_to_dir(&PathBuf::from(folder_path), to_parent)?;
+    // The move already happened, a visit that wasn't counted isn't worth
+    // failing it for
+    let _ = database
+        .record_visit(state_guard.get_path(), FileType::Folder)
+        .await;
+    Ok(())
 }
 
 #[tauri::command]
 async fn move_to_parent_folder(
-    state: tauri::State<'_, OuterCurrentDir>,
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
 ) -> Result<String, CurrentDirError> {
-    let mut state_guard = state.0.lock().await;
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
 
     state_guard.move_to_parent_dir()
 }
 
 #[tauri::command]
-async fn current_dir_is_root(state: tauri::State<'_, OuterCurrentDir>) -> Result<bool, ()> {
-    let state_guard = state.0.lock().await;
+async fn go_back(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+) -> Result<HistoryEntry, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
+    state_guard.go_back()
+}
+
+#[tauri::command]
+async fn go_forward(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+) -> Result<HistoryEntry, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
+    state_guard.go_forward()
+}
+
+#[tauri::command]
+async fn get_history(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+) -> Result<History, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    Ok(state_guard.get_history().clone())
+}
+
+#[tauri::command]
+async fn save_view_state(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    selected: Option<String>,
+    scroll: f64,
+) -> Result<(), CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let mut state_guard = tab.lock().await;
+    state_guard.save_view_state(selected, scroll);
+    Ok(())
+}
+
+#[tauri::command]
+async fn complete_path(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    partial: String,
+) -> Result<Vec<FileData>, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    state_guard.complete_path(&partial)
+}
+
+#[tauri::command]
+async fn current_dir_is_root(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+) -> Result<bool, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
     Ok(state_guard.current_dir_is_root())
 }
 
 #[tauri::command]
 async fn find_file(
-    state: tauri::State<'_, OuterCurrentDir>,
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
     name: String,
     files: bool,
     folders: bool,
     links: bool,
     exact: bool,
+    boost_frecent: Option<bool>,
 ) -> Result<Vec<FileData>, CurrentDirError> {
-    let state_guard = state.0.lock().await;
-    state_guard
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    let mut results = state_guard
         .search_files(name, files, folders, links, exact)
+        .await?;
+
+    if boost_frecent.unwrap_or(false) {
+        frecency::boost(&mut results, &database.frecency_scores().await?);
+    }
+    Ok(results)
+}
+
+#[tauri::command]
+async fn record_file_open(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    path: String,
+) -> Result<(), CurrentDirError> {
+    database
+        .record_visit(Path::new(&path), FileType::File)
         .await
 }
 
+#[tauri::command]
+async fn jump(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    query: String,
+) -> Result<PathBuf, CurrentDirError> {
+    database.jump(&query).await
+}
+
 #[tauri::command]
 async fn create_file(
-    state: tauri::State<'_, OuterCurrentDir>,
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
     filename: String,
     filetype: String,
 ) -> Result<(), CurrentDirError> {
-    let state_guard = state.0.lock().await;
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
     state_guard.create_file(filename, filetype).await
 }
 
 #[tauri::command]
 async fn delete_file(
-    state: tauri::State<'_, OuterCurrentDir>,
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
     filename: String,
     filetype: String,
 ) -> Result<(), CurrentDirError> {
-    let state_guard = state.0.lock().await;
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
     state_guard.delete_file(filename, filetype).await
 }
 
+#[tauri::command]
+async fn rename_file(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    filename: String,
+    new_name: String,
+) -> Result<PathBuf, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    state_guard.rename_file(filename, new_name)
+}
+
+/// `destination` can be a local folder or a remote one such as
+/// `sftp://user@host/path`
+#[tauri::command]
+async fn copy_files(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    filenames: Vec<String>,
+    destination: String,
+) -> Result<Copied, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    state_guard.copy_entries(filenames, Path::new(&destination))
+}
+
+/// Saves the password for a server url such as `davs://user@host` in the
+/// secret store of the desktop
+#[tauri::command]
+async fn save_remote_password(url: String, password: String) -> Result<(), CurrentDirError> {
+    let (remote, _) =
+        RemoteUrl::parse(Path::new(&url)).ok_or(CurrentDirError::NotARemoteUrl { url })?;
+    credentials::save_password(&remote, &password)
+}
+
+#[tauri::command]
+async fn forget_remote_password(url: String) -> Result<(), CurrentDirError> {
+    let (remote, _) =
+        RemoteUrl::parse(Path::new(&url)).ok_or(CurrentDirError::NotARemoteUrl { url })?;
+    credentials::forget_password(&remote)
+}
+
+/// Copies entries out of the archive the tab is browsing
+#[tauri::command]
+async fn extract_entries(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    filenames: Vec<String>,
+    destination: String,
+) -> Result<Vec<PathBuf>, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    state_guard.extract_entries(filenames, Path::new(&destination))
+}
+
+/// What a job that succeeded sends along with its "job-finished" event
+#[derive(Default)]
+struct JobOutcome {
+    path: Option<PathBuf>,
+    skipped: Vec<PathBuf>,
+}
+
+impl JobOutcome {
+    fn at(path: PathBuf) -> Self {
+        JobOutcome {
+            path: Some(path),
+            skipped: vec![],
+        }
+    }
+}
+
+/// Starts a job and runs `task` for it in the background. The job is
+/// finished and a "job-finished" event is sent once `task` is done, the
+/// events in between are up to `task`.
+fn run_job<F, T>(window: tauri::Window, jobs: &Jobs, task: T) -> JobId
+where
+    T: FnOnce(Job, tauri::Window) -> F,
+    F: Future<Output = Result<JobOutcome, CurrentDirError>> + Send + 'static,
+{
+    let job = jobs.0.lock().unwrap().start();
+    let job_id = job.id;
+    let registry = Arc::clone(&jobs.0);
+    let task = task(job, window.clone());
+
+    tokio::spawn(async move {
+        let result = task.await;
+        registry.lock().unwrap().finish(job_id);
+        let finished = match result {
+            Ok(outcome) => JobFinished {
+                job_id,
+                path: outcome.path,
+                skipped: outcome.skipped,
+                error: None,
+            },
+            Err(err) => JobFinished {
+                job_id,
+                path: None,
+                skipped: vec![],
+                error: Some(err.to_string()),
+            },
+        };
+        let _ = window.emit("job-finished", finished);
+    });
+    job_id
+}
+
+/// Like `run_job`, for tasks that block. Their progress is sent as
+/// "job-progress" events.
+fn run_blocking_job<T>(window: tauri::Window, jobs: &Jobs, task: T) -> JobId
+where
+    T: FnOnce(
+            &Job,
+            &tauri::Window,
+            &mut dyn FnMut(JobProgress),
+        ) -> Result<JobOutcome, CurrentDirError>
+        + Send
+        + 'static,
+{
+    run_job(window, jobs, |job, window| async move {
+        tokio::task::spawn_blocking(move || {
+            let progress_window = window.clone();
+            task(&job, &window, &mut |progress| {
+                let _ = progress_window.emit("job-progress", progress);
+            })
+        })
+        .await
+        .unwrap_or(Err(CurrentDirError::Cancelled))
+    })
+}
+
+/// Progress is sent as "job-progress" events and the result as a
+/// "job-finished" event
+#[tauri::command]
+async fn compress_files(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    files: Vec<FileData>,
+    destination: String,
+    format: CompressFormat,
+    level: Option<u32>,
+) -> Result<JobId, CurrentDirError> {
+    Ok(run_blocking_job(
+        window,
+        &jobs,
+        move |job, _, on_progress| {
+            let compressed = compression::compress(
+                &files,
+                Path::new(&destination),
+                format,
+                level,
+                job,
+                on_progress,
+            )?;
+            Ok(JobOutcome {
+                path: Some(compressed.path),
+                skipped: compressed.skipped,
+            })
+        },
+    ))
+}
+
+/// Without a destination the archive is extracted into a new folder next to
+/// it
+#[tauri::command]
+async fn extract_archive(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    archive: String,
+    destination: Option<String>,
+) -> Result<JobId, CurrentDirError> {
+    Ok(run_blocking_job(
+        window,
+        &jobs,
+        move |job, _, on_progress| {
+            let extracted = archive::extract_archive(
+                Path::new(&archive),
+                destination.as_deref().map(Path::new),
+                job,
+                on_progress,
+            )?;
+            Ok(JobOutcome {
+                path: Some(extracted.path),
+                skipped: extracted.skipped,
+            })
+        },
+    ))
+}
+
+#[tauri::command]
+async fn cancel_job(jobs: tauri::State<'_, Jobs>, job_id: JobId) -> Result<(), CurrentDirError> {
+    jobs.0.lock().unwrap().cancel(job_id)
+}
+
+/// Partial results are sent as "disk-usage-progress" events. The finished
+/// scan is saved, so that `get_disk_usage` answers right away for `path` and
+/// every folder under it.
+#[tauri::command]
+async fn analyze_disk_usage(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    path: String,
+) -> Result<JobId, CurrentDirError> {
+    let database = Arc::clone(&database);
+
+    Ok(run_job(window, &jobs, |job, window| async move {
+        let root = PathBuf::from(&path);
+        let folders = tokio::task::spawn_blocking(move || {
+            diskusage::analyze(&root, &job, |progress| {
+                let _ = window.emit("disk-usage-progress", progress);
+            })
+        })
+        .await
+        .unwrap_or(Err(CurrentDirError::Cancelled))?;
+        // Saved under the canonical root, like the paths of the tabs
+        let root = folders
+            .last()
+            .map_or(PathBuf::from(&path), |root| root.path.clone());
+        database.save_disk_usage(&root, folders).await?;
+        Ok(JobOutcome::at(PathBuf::from(&path)))
+    }))
+}
+
+/// The saved scan of the current folder of the tab, `None` when the folder
+/// hasn't been analyzed
+#[tauri::command]
+async fn get_disk_usage(
+    tabs: tauri::State<'_, Tabs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    tab_id: TabId,
+) -> Result<Option<FolderUsage>, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    database.get_disk_usage(state_guard.get_path()).await
+}
+
+/// The groups are sent as a "duplicates-found" event before the job finishes
+#[tauri::command]
+async fn find_duplicates(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    roots: Vec<String>,
+    options: Option<DuplicateOptions>,
+) -> Result<JobId, CurrentDirError> {
+    let database = Arc::clone(&database);
+
+    Ok(run_job(window, &jobs, |job, window| async move {
+        let progress_window = window.clone();
+        let duplicates = duplicates::find_duplicates(
+            &database,
+            roots.into_iter().map(PathBuf::from).collect(),
+            options.unwrap_or_default(),
+            job,
+            move |progress| {
+                let _ = progress_window.emit("job-progress", progress);
+            },
+        )
+        .await?;
+        let _ = window.emit("duplicates-found", duplicates);
+        Ok(JobOutcome::default())
+    }))
+}
+
+/// The hashes are sent as a "checksums-computed" event before the job
+/// finishes
+#[tauri::command]
+async fn compute_checksums(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    paths: Vec<String>,
+    algorithms: Vec<HashAlgorithm>,
+) -> Result<JobId, CurrentDirError> {
+    Ok(run_blocking_job(
+        window,
+        &jobs,
+        move |job, window, on_progress| {
+            let paths = paths.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+            let checksums = checksums::compute(&paths, &algorithms, job, on_progress)?;
+            let _ = window.emit("checksums-computed", checksums);
+            Ok(JobOutcome::default())
+        },
+    ))
+}
+
+/// Once every entry is checked, the whole result is sent as one
+/// "manifest-verified" event before the job finishes
+#[tauri::command]
+async fn verify_manifest(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    manifest: String,
+) -> Result<JobId, CurrentDirError> {
+    Ok(run_blocking_job(
+        window,
+        &jobs,
+        move |job, window, on_progress| {
+            let check = checksums::verify_manifest(Path::new(&manifest), job, on_progress)?;
+            let _ = window.emit("manifest-verified", check);
+            Ok(JobOutcome::at(PathBuf::from(&manifest)))
+        },
+    ))
+}
+
+#[tauri::command]
+async fn generate_manifest(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    folder: String,
+    algorithm: HashAlgorithm,
+) -> Result<JobId, CurrentDirError> {
+    Ok(run_blocking_job(
+        window,
+        &jobs,
+        move |job, _, on_progress| {
+            checksums::generate_manifest(Path::new(&folder), algorithm, job, on_progress)
+                .map(JobOutcome::at)
+        },
+    ))
+}
+
+/// The tree of differences is sent as a "folders-compared" event before the
+/// job finishes
+#[tauri::command]
+async fn compare_folders(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    left: String,
+    right: String,
+    mode: Option<CompareMode>,
+) -> Result<JobId, CurrentDirError> {
+    Ok(run_blocking_job(
+        window,
+        &jobs,
+        move |job, window, on_progress| {
+            let comparison = comparison::compare(
+                Path::new(&left),
+                Path::new(&right),
+                mode.unwrap_or_default(),
+                job,
+                on_progress,
+            )?;
+            let _ = window.emit("folders-compared", comparison);
+            Ok(JobOutcome::default())
+        },
+    ))
+}
+
+/// Mounted drives and network shares, for starting somewhere else than in
+/// the current folder
+#[tauri::command]
+async fn list_volumes() -> Result<Vec<Volume>, CurrentDirError> {
+    volumes::list_volumes().await
+}
+
+/// The size of a folder is counted by a job that sends its partial results
+/// as "folder-size-progress" events
+#[tauri::command]
+async fn get_file_properties(
+    window: tauri::Window,
+    jobs: tauri::State<'_, Jobs>,
+    path: String,
+) -> Result<FileProperties, CurrentDirError> {
+    let mut file_properties = properties::get_file_properties(Path::new(&path))?;
+
+    if file_properties.filetype == FileType::Folder {
+        file_properties.size_job = Some(run_blocking_job(window, &jobs, move |job, window, _| {
+            properties::folder_size(Path::new(&path), job, |progress| {
+                let _ = window.emit("folder-size-progress", progress);
+            })?;
+            Ok(JobOutcome::at(PathBuf::from(path)))
+        }));
+    }
+    Ok(file_properties)
+}
+
+#[tauri::command]
+async fn change_mode(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    filenames: Vec<String>,
+    mode: String,
+    recursive: bool,
+    dry_run: bool,
+) -> Result<Vec<PermissionChange>, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    state_guard.change_mode(filenames, mode, recursive, dry_run)
+}
+
+#[tauri::command]
+async fn change_owner(
+    tabs: tauri::State<'_, Tabs>,
+    tab_id: TabId,
+    filenames: Vec<String>,
+    owner: Option<String>,
+    group: Option<String>,
+    recursive: bool,
+    dry_run: bool,
+) -> Result<Vec<PermissionChange>, CurrentDirError> {
+    let tab = tabs.get(tab_id).await?;
+    let state_guard = tab.lock().await;
+    state_guard.change_owner(filenames, owner, group, recursive, dry_run)
+}
+
+#[tauri::command]
+async fn request_thumbnails(
+    window: tauri::Window,
+    thumbnail_service: tauri::State<'_, Arc<ThumbnailService>>,
+    paths: Vec<String>,
+) -> Result<(), ()> {
+    thumbnail_service.generate(
+        paths.into_iter().map(PathBuf::from).collect(),
+        move |path| {
+            let _ = window.emit("thumbnail-ready", path);
+        },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+async fn preview_file(path: String, max_kb: Option<usize>) -> Result<FilePreview, CurrentDirError> {
+    preview::preview_file(
+        Path::new(&path),
+        max_kb.unwrap_or(preview::DEFAULT_PREVIEW_KB),
+    )
+}
+
+#[tauri::command]
+async fn list_bookmarks(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+) -> Result<Vec<Bookmark>, CurrentDirError> {
+    database.list_bookmarks().await
+}
+
+#[tauri::command]
+async fn add_bookmark(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    name: String,
+    path: String,
+    icon: Option<String>,
+    group: Option<String>,
+) -> Result<Bookmark, CurrentDirError> {
+    database
+        .add_bookmark(name, PathBuf::from(path), icon, group)
+        .await
+}
+
+#[tauri::command]
+async fn update_bookmark(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    id: i64,
+    name: String,
+    icon: Option<String>,
+    group: Option<String>,
+) -> Result<(), CurrentDirError> {
+    database.update_bookmark(id, name, icon, group).await
+}
+
+#[tauri::command]
+async fn remove_bookmark(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    id: i64,
+) -> Result<(), CurrentDirError> {
+    database.remove_bookmark(id).await
+}
+
+#[tauri::command]
+async fn move_bookmark(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+    id: i64,
+    position: i64,
+) -> Result<(), CurrentDirError> {
+    database.move_bookmark(id, position).await
+}
+
+#[tauri::command]
+async fn import_gtk_bookmarks(
+    database: tauri::State<'_, Arc<AppDatabase>>,
+) -> Result<usize, CurrentDirError> {
+    database
+        .import_gtk_bookmarks(&bookmarks::gtk_bookmarks_file())
+        .await
+}
+
 #[tokio::main]
 async fn main() {
+    let app_database = AppDatabase::open(database::APP_DATABASE_FILE)
+        .await
+        .expect("cannot open the app database");
+    let file_cache = FileCache::new().await;
+    let mut tab_registry = TabRegistry::new(Arc::clone(&file_cache));
+    tab_registry
+        .open_tab(Path::new("."))
+        .expect("cannot open the first tab");
+
     tauri::Builder::default()
-        .manage(OuterCurrentDir(Arc::new(Mutex::new(
-            CurrentDir::new(Path::new(".")).await,
-        ))))
+        .manage(Arc::clone(&file_cache))
+        .manage(Arc::new(app_database))
+        .manage(Tabs(Mutex::new(tab_registry)))
+        .manage(Jobs::default())
+        .manage(Arc::new(ThumbnailService::default()))
+        // Only thumbnails that are already up to date are served, so that
+        // the webview is never blocked. The others are generated on the
+        // thumbnail pool by `request_thumbnails`.
+        .register_uri_scheme_protocol(thumbnails::PROTOCOL, |app, request| {
+            let thumbnail = thumbnails::path_from_protocol_uri(request.uri())
+                .and_then(|path| app.state::<Arc<ThumbnailService>>().get_existing(&path));
+
+            match thumbnail {
+                Some(thumbnail) => ResponseBuilder::new()
+                    .mimetype("image/png")
+                    .body(std::fs::read(thumbnail)?),
+                None => ResponseBuilder::new().status(404).body(Vec::new()),
+            }
+        })
         .invoke_handler(tauri::generate_handler![
+            open_tab,
+            close_tab,
+            list_tabs,
             get_current_folder,
+            get_folder_window,
+            watch_folder,
+            get_sort_mode,
+            set_sort_mode,
             move_to_folder,
             move_to_parent_folder,
+            go_back,
+            go_forward,
+            get_history,
+            save_view_state,
+            complete_path,
             current_dir_is_root,
             find_file,
+            record_file_open,
+            jump,
             create_file,
-            delete_file
+            delete_file,
+            rename_file,
+            copy_files,
+            save_remote_password,
+            forget_remote_password,
+            extract_entries,
+            compress_files,
+            extract_archive,
+            cancel_job,
+            analyze_disk_usage,
+            get_disk_usage,
+            find_duplicates,
+            compute_checksums,
+            verify_manifest,
+            generate_manifest,
+            compare_folders,
+            list_volumes,
+            get_file_properties,
+            change_mode,
+            change_owner,
+            request_thumbnails,
+            preview_file,
+            list_bookmarks,
+            add_bookmark,
+            update_bookmark,
+            remove_bookmark,
+            move_bookmark,
+            import_gtk_bookmarks
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .setup(move |app| {
+            let handle = app.handle();
+            let indexer = file_cache.start_indexing(move |err| {
+                let _ = handle.emit_all("indexing-failed", err.to_string());
+            });
+            app.manage(Indexing(std::sync::Mutex::new(Some(indexer))));
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let indexer = app.state::<Indexing>().0.lock().unwrap().take();
+                if let Some(indexer) = indexer {
+                    indexer.shutdown();
+                    // A walk stuck on a slow mount shouldn't keep the app open
+                    let _ = tokio::task::block_in_place(|| {
+                        tokio::runtime::Handle::current()
+                            .block_on(tokio::time::timeout(INDEXER_TIMEOUT, indexer.join()))
+                    });
+                }
+            }
+        });
 }
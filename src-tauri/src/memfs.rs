@@ -0,0 +1,509 @@
+use super::{
+    vfs::{OnChanges, VfsBackend, VfsEntry, VfsMetadata, VfsWatcher},
+    watcher::FolderChange,
+    FileType,
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Cursor, Read},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Links are followed this many times before giving up, like the kernel does
+const MAX_LINKS: usize = 40;
+
+#[derive(Debug, Clone)]
+enum Contents {
+    Folder,
+    File(Vec<u8>),
+    Link(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    contents: Contents,
+    modified: i64,
+    inode: u64,
+}
+
+impl Node {
+    fn metadata(&self) -> VfsMetadata {
+        let (filetype, size) = match &self.contents {
+            Contents::Folder => (FileType::Folder, 0),
+            Contents::File(bytes) => (FileType::File, bytes.len() as u64),
+            Contents::Link(target) => (FileType::Link, target.as_os_str().len() as u64),
+        };
+        VfsMetadata {
+            filetype,
+            size,
+            modified: self.modified,
+            inode: self.inode,
+            special: None,
+        }
+    }
+}
+
+struct Watch {
+    folder: Mutex<PathBuf>,
+    changed: AtomicBool,
+    on_changes: OnChanges,
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watch")
+            .field("folder", &self.folder)
+            .field("changed", &self.changed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct MemoryWatcher(Arc<Watch>);
+
+impl VfsWatcher for MemoryWatcher {
+    fn follow(&mut self, folder: &Path) -> io::Result<()> {
+        *self.0.folder.lock().unwrap() = folder.to_owned();
+        Ok(())
+    }
+
+    fn take_changed(&self) -> bool {
+        self.0.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} doesn't exist", path.display()),
+    )
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{} already exists", path.display()),
+    )
+}
+
+fn wrong_type(path: &Path, expected: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} isn't a {}", path.display(), expected),
+    )
+}
+
+/// Resolves `.` and `..` without looking at the nodes. Relative paths start
+/// from the root.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+#[derive(Debug)]
+struct State {
+    nodes: BTreeMap<PathBuf, Node>,
+    next_inode: u64,
+    watches: Vec<Weak<Watch>>,
+}
+
+impl State {
+    fn node(&self, path: &Path) -> io::Result<&Node> {
+        self.nodes.get(path).ok_or_else(|| not_found(path))
+    }
+
+    fn is_folder(&self, path: &Path) -> io::Result<()> {
+        match self.node(path)?.contents {
+            Contents::Folder => Ok(()),
+            _ => Err(wrong_type(path, "folder")),
+        }
+    }
+
+    fn insert(&mut self, path: &Path, contents: Contents) -> io::Result<VfsMetadata> {
+        self.is_folder(path.parent().ok_or_else(|| already_exists(path))?)?;
+        if self.nodes.contains_key(path) {
+            return Err(already_exists(path));
+        }
+        self.next_inode += 1;
+        let node = Node {
+            contents,
+            modified: now(),
+            inode: self.next_inode,
+        };
+        let metadata = node.metadata();
+        self.nodes.insert(path.to_owned(), node);
+        Ok(metadata)
+    }
+
+    /// Follows links at the end of the path
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut path = normalize(path);
+        for _ in 0..MAX_LINKS {
+            match &self.node(&path)?.contents {
+                Contents::Link(target) => {
+                    path = normalize(&path.parent().unwrap_or(Path::new("/")).join(target))
+                }
+                _ => return Ok(path),
+            }
+        }
+        Err(io::Error::other(format!(
+            "too many levels of links in {}",
+            path.display()
+        )))
+    }
+
+    /// The watches of the folder that `path` is in
+    fn watches_of(&mut self, path: &Path) -> Vec<Arc<Watch>> {
+        self.watches.retain(|watch| watch.strong_count() > 0);
+        self.watches
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|watch| Some(watch.folder.lock().unwrap().as_path()) == path.parent())
+            .collect()
+    }
+}
+
+/// Keeps everything in memory, for tests and as a reference for how other
+/// backends should behave. Changes are reported right away, on the thread
+/// that made them.
+#[derive(Debug)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        let root = Node {
+            contents: Contents::Folder,
+            modified: now(),
+            inode: 1,
+        };
+        MemoryBackend {
+            state: Mutex::new(State {
+                nodes: BTreeMap::from([(PathBuf::from("/"), root)]),
+                next_inode: 1,
+                watches: vec![],
+            }),
+        }
+    }
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+
+    /// Creates the file or replaces its contents
+    pub fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let path = normalize(path);
+        let mut state = self.state.lock().unwrap();
+        let (metadata, modified) = match state.nodes.get_mut(&path) {
+            Some(node) => match &mut node.contents {
+                Contents::File(bytes) => {
+                    *bytes = contents.to_vec();
+                    node.modified = now();
+                    (node.metadata(), true)
+                }
+                _ => return Err(wrong_type(&path, "file")),
+            },
+            None => (
+                state.insert(&path, Contents::File(contents.to_vec()))?,
+                false,
+            ),
+        };
+        let watches = state.watches_of(&path);
+        drop(state);
+
+        if let Some(file) = self.file_data(&path, &metadata) {
+            let change = match modified {
+                true => FolderChange::Modified { file },
+                false => FolderChange::Created { file },
+            };
+            report(&watches, change);
+        }
+        Ok(())
+    }
+
+    /// Creates a link at `path` that points to `target`
+    pub fn symlink(&self, target: &Path, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut state = self.state.lock().unwrap();
+        let metadata = state.insert(&path, Contents::Link(target.to_owned()))?;
+        let watches = state.watches_of(&path);
+        drop(state);
+
+        if let Some(file) = self.file_data(&path, &metadata) {
+            report(&watches, FolderChange::Created { file });
+        }
+        Ok(())
+    }
+}
+
+/// Called without holding the lock, so that the callbacks can use the backend
+fn report(watches: &[Arc<Watch>], change: FolderChange) {
+    for watch in watches {
+        watch.changed.store(true, Ordering::SeqCst);
+        (watch.on_changes)(vec![change.clone()]);
+    }
+}
+
+impl VfsBackend for MemoryBackend {
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.state.lock().unwrap().resolve(path)
+    }
+
+    fn list(&self, folder: &Path) -> io::Result<Vec<VfsEntry>> {
+        let folder = normalize(folder);
+        let state = self.state.lock().unwrap();
+        state.is_folder(&folder)?;
+        Ok(state
+            .nodes
+            .range(folder.clone()..)
+            .filter(|(path, _)| path.parent() == Some(folder.as_path()))
+            .map(|(path, node)| VfsEntry {
+                path: path.clone(),
+                metadata: node.metadata(),
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        let path = normalize(path);
+        Ok(self.state.lock().unwrap().node(&path)?.metadata())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let state = self.state.lock().unwrap();
+        let path = state.resolve(path)?;
+        match &state.node(&path)?.contents {
+            Contents::File(bytes) => Ok(Box::new(Cursor::new(bytes.clone()))),
+            _ => Err(wrong_type(&path, "file")),
+        }
+    }
+
+    fn create(&self, path: &Path, filetype: FileType) -> io::Result<()> {
+        let contents = match filetype {
+            FileType::Folder => Contents::Folder,
+            FileType::File => Contents::File(vec![]),
+            FileType::Link => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "links can't be created without a target",
+                ))
+            }
+        };
+        let path = normalize(path);
+        let mut state = self.state.lock().unwrap();
+        let metadata = state.insert(&path, contents)?;
+        let watches = state.watches_of(&path);
+        drop(state);
+
+        if let Some(file) = self.file_data(&path, &metadata) {
+            report(&watches, FolderChange::Created { file });
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut state = self.state.lock().unwrap();
+        let has_children = state
+            .nodes
+            .range(path.clone()..)
+            .nth(1)
+            .is_some_and(|(child, _)| child.parent() == Some(path.as_path()));
+        if path.parent().is_none() || has_children {
+            return Err(io::Error::other(format!(
+                "{} isn't an empty folder",
+                path.display()
+            )));
+        }
+        state.nodes.remove(&path).ok_or_else(|| not_found(&path))?;
+        let watches = state.watches_of(&path);
+        drop(state);
+
+        report(&watches, FolderChange::Removed { path });
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut state = self.state.lock().unwrap();
+        let metadata = state.node(&from)?.metadata();
+        if from.parent().is_none() || to.starts_with(&from) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} can't be moved into itself", from.display()),
+            ));
+        }
+        state.is_folder(to.parent().ok_or_else(|| already_exists(&to))?)?;
+        if state.nodes.contains_key(&to) {
+            return Err(already_exists(&to));
+        }
+
+        let moved = state
+            .nodes
+            .keys()
+            .filter(|path| path.starts_with(&from))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        for path in moved {
+            let node = state.nodes.remove(&path).ok_or_else(|| not_found(&path))?;
+            let relative = path.strip_prefix(&from).unwrap_or(Path::new(""));
+            state
+                .nodes
+                .insert(to.join(relative).components().collect(), node);
+        }
+        let old_watches = state.watches_of(&from);
+        let new_watches = state.watches_of(&to);
+        drop(state);
+
+        let Some(file) = self.file_data(&to, &metadata) else {
+            return Ok(());
+        };
+        match from.parent() == to.parent() {
+            true => report(&new_watches, FolderChange::Renamed { from, file }),
+            false => {
+                report(&old_watches, FolderChange::Removed { path: from });
+                report(&new_watches, FolderChange::Created { file });
+            }
+        }
+        Ok(())
+    }
+
+    fn store(&self, path: &Path, contents: &mut dyn Read) -> io::Result<u64> {
+        let mut bytes = vec![];
+        contents.read_to_end(&mut bytes)?;
+        self.write(path, &bytes)?;
+        Ok(bytes.len() as u64)
+    }
+
+    fn watch(&self, folder: &Path, on_changes: OnChanges) -> io::Result<Box<dyn VfsWatcher>> {
+        let watch = Arc::new(Watch {
+            folder: Mutex::new(normalize(folder)),
+            changed: AtomicBool::new(false),
+            on_changes,
+        });
+        self.state
+            .lock()
+            .unwrap()
+            .watches
+            .push(Arc::downgrade(&watch));
+        Ok(Box::new(MemoryWatcher(watch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryBackend;
+    use crate::{
+        filecache::FileCache, listing::ListingOptions, sorting::SortMode, vfs::VfsBackend,
+        watcher::FolderChange, CurrentDir, FileType,
+    };
+    use std::{io::Read, path::Path, sync::mpsc, sync::Arc};
+
+    #[test]
+    fn renamed_folders_take_their_contents_along() {
+        let backend = MemoryBackend::new();
+        backend.create(Path::new("/a"), FileType::Folder).unwrap();
+        backend.write(Path::new("/a/file.txt"), b"hello").unwrap();
+        backend
+            .symlink(Path::new("a/file.txt"), Path::new("/link"))
+            .unwrap();
+
+        assert!(backend.remove(Path::new("/a")).is_err());
+        assert!(backend.rename(Path::new("/a"), Path::new("/a/b")).is_err());
+        backend.rename(Path::new("/a"), Path::new("/b")).unwrap();
+        assert!(backend.stat(Path::new("/a/file.txt")).is_err());
+        assert_eq!(backend.stat(Path::new("/b/file.txt")).unwrap().size, 5);
+
+        backend
+            .symlink(Path::new("b/file.txt"), Path::new("/b/../other"))
+            .unwrap();
+        let mut contents = String::new();
+        backend
+            .open(Path::new("/other"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "hello");
+        assert!(backend.open(Path::new("/link")).is_err());
+    }
+
+    #[tokio::test]
+    async fn current_dir_works_without_the_disk() {
+        let backend = Arc::new(MemoryBackend::new());
+        backend
+            .create(Path::new("/home"), FileType::Folder)
+            .unwrap();
+        backend
+            .write(Path::new("/home/notes.md"), b"# Notes")
+            .unwrap();
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let mut current_dir =
+            CurrentDir::with_backend(Path::new("/home"), cache, backend.clone()).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        current_dir
+            .watch(move |changes| {
+                let _ = sender.send(changes);
+            })
+            .unwrap();
+
+        current_dir
+            .create_file("src".to_string(), "folder".to_string())
+            .await
+            .unwrap();
+        let created = receiver.try_recv().unwrap();
+        let options = ListingOptions::default();
+        let window = current_dir
+            .get_folder_window(&SortMode::default(), &options, 0, 10)
+            .unwrap();
+        current_dir
+            .delete_file("notes.md".to_string(), "file".to_string())
+            .await
+            .unwrap();
+        current_dir
+            .move_to_dir(Path::new("/home/src"), false)
+            .unwrap();
+        backend.write(Path::new("/home/src/main.rs"), b"").unwrap();
+        let inside = current_dir
+            .get_folder_data(&SortMode::default(), &options)
+            .unwrap();
+
+        assert!(matches!(&created[..], [FolderChange::Created { file }] if file.name == "src"));
+        assert_eq!(window.total, 2);
+        assert_eq!(window.files[0].name, "src");
+        assert_eq!(window.files[1].mime.as_deref(), Some("text/markdown"));
+        assert!(matches!(
+            &receiver.try_recv().unwrap()[..],
+            [FolderChange::Removed { path }] if path == Path::new("/home/notes.md")
+        ));
+        assert!(matches!(
+            &receiver.try_recv().unwrap()[..],
+            [FolderChange::Created { file }] if file.name == "main.rs"
+        ));
+        assert_eq!(inside.name, "src");
+        assert_eq!(inside.files.len(), 1);
+    }
+}
@@ -0,0 +1,371 @@
+use super::{platform, FileType};
+use std::{collections::HashMap, fs, io::Read, path::Path, sync::Mutex};
+
+pub const DIRECTORY: &str = "inode/directory";
+pub const SYMLINK: &str = "inode/symlink";
+pub const FIFO: &str = "inode/fifo";
+pub const SOCKET: &str = "inode/socket";
+pub const CHAR_DEVICE: &str = "inode/chardevice";
+pub const BLOCK_DEVICE: &str = "inode/blockdevice";
+pub const UNKNOWN: &str = "application/octet-stream";
+pub const TEXT: &str = "text/plain";
+
+/// Enough to reach the tar header magic at offset 257
+const SNIFF_LENGTH: usize = 512;
+/// The cache is emptied once it grows past this, rather than tracking usage
+const MAX_CACHED: usize = 100_000;
+
+/// Formats such as docx and jar are zip files, so the extension tells more
+/// about them than their content does
+const CONTAINERS: &[&str] = &["application/zip", "application/xml"];
+
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (8, b"WAVE", "audio/wav"),
+    (8, b"AVI ", "video/x-msvideo"),
+    (0, b"BM", "image/bmp"),
+    (0, b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"<?xml", "application/xml"),
+];
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("avi", "video/x-msvideo"),
+    ("bmp", "image/bmp"),
+    ("bz2", "application/x-bzip2"),
+    ("c", "text/x-csrc"),
+    ("cpp", "text/x-c++src"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("deb", "application/vnd.debian.binary-package"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("flac", "audio/flac"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("h", "text/x-chdr"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("iso", "application/x-iso9660-image"),
+    ("jar", "application/java-archive"),
+    ("java", "text/x-java"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("md", "text/markdown"),
+    ("mkv", "video/x-matroska"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ogg", "audio/ogg"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("py", "text/x-python"),
+    ("rs", "text/rust"),
+    ("sh", "application/x-shellscript"),
+    ("sql", "application/sql"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("toml", "application/toml"),
+    ("ts", "text/x-typescript"),
+    ("txt", "text/plain"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("xml", "application/xml"),
+    ("xz", "application/x-xz"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+    ("zst", "application/zstd"),
+];
+
+/// Entries that `FileType` counts as files but that must never be opened
+/// for sniffing. Opening a FIFO without a writer blocks, and opening a device
+/// can have side effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFile {
+    Fifo,
+    Socket,
+    CharDevice,
+    BlockDevice,
+}
+
+impl SpecialFile {
+    #[cfg(unix)]
+    pub fn of(file_type: &fs::FileType) -> Option<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+            Some(SpecialFile::Fifo)
+        } else if file_type.is_socket() {
+            Some(SpecialFile::Socket)
+        } else if file_type.is_char_device() {
+            Some(SpecialFile::CharDevice)
+        } else if file_type.is_block_device() {
+            Some(SpecialFile::BlockDevice)
+        } else {
+            None
+        }
+    }
+
+    /// Pipes and devices aren't listed as files elsewhere
+    #[cfg(not(unix))]
+    pub fn of(_file_type: &fs::FileType) -> Option<Self> {
+        None
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            SpecialFile::Fifo => FIFO,
+            SpecialFile::Socket => SOCKET,
+            SpecialFile::CharDevice => CHAR_DEVICE,
+            SpecialFile::BlockDevice => BLOCK_DEVICE,
+        }
+    }
+}
+
+/// Guesses the MIME type of a file from its extension only
+pub fn from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+}
+
+/// Guesses the MIME type from the first bytes of a file
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    MAGIC
+        .iter()
+        .find(|(offset, magic, _)| bytes.get(*offset..offset + magic.len()) == Some(*magic))
+        .map(|(_, _, mime)| *mime)
+}
+
+/// Text has no NUL bytes and is valid UTF-8, except possibly for a character
+/// cut in half at the end of the buffer
+pub fn looks_like_text(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    }
+}
+
+/// Combines content sniffing with the extension of the file. Only regular
+/// files are opened.
+pub fn detect(path: &Path) -> &'static str {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => return DIRECTORY,
+        Ok(metadata) if !metadata.is_file() => {
+            return SpecialFile::of(&metadata.file_type()).map_or(UNKNOWN, |special| special.mime())
+        }
+        _ => {}
+    }
+    let mut buffer = Vec::with_capacity(SNIFF_LENGTH);
+    let read = fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_LENGTH as u64).read_to_end(&mut buffer))
+        .is_ok();
+    let by_extension = from_extension(path);
+
+    match sniff(&buffer) {
+        Some(sniffed) if !CONTAINERS.contains(&sniffed) => sniffed,
+        sniffed => by_extension
+            .or(sniffed)
+            .unwrap_or(if read && looks_like_text(&buffer) {
+                TEXT
+            } else {
+                UNKNOWN
+            }),
+    }
+}
+
+/// Remembers the detected types by inode and modification time, so that
+/// listing the same folder again doesn't read every file again
+#[derive(Debug, Default)]
+pub struct MimeCache {
+    entries: Mutex<HashMap<(u64, i64), &'static str>>,
+}
+
+impl MimeCache {
+    pub fn get(&self, path: &Path, metadata: &fs::Metadata) -> &'static str {
+        self.lookup(
+            path,
+            FileType::from(metadata.file_type()),
+            SpecialFile::of(&metadata.file_type()),
+            (
+                platform::inode(metadata).unwrap_or(0),
+                platform::modified(metadata),
+            ),
+        )
+    }
+
+    /// `key` is the inode and modification time of the file. Files without
+    /// an inode aren't cached.
+    pub fn lookup(
+        &self,
+        path: &Path,
+        filetype: FileType,
+        special: Option<SpecialFile>,
+        key: (u64, i64),
+    ) -> &'static str {
+        match (filetype, special) {
+            (FileType::Folder, _) => return DIRECTORY,
+            (FileType::Link, _) => return SYMLINK,
+            (FileType::File, Some(special)) => return special.mime(),
+            (FileType::File, None) => {}
+        }
+
+        if key.0 == 0 {
+            return detect(path);
+        }
+        if let Some(mime) = self.entries.lock().unwrap().get(&key) {
+            return mime;
+        }
+
+        let mime = detect(path);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHED {
+            entries.clear();
+        }
+        entries.insert(key, mime);
+        mime
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, from_extension, looks_like_text, sniff, MimeCache};
+    use crate::{filecache::FileCache, listing::ListingOptions, sorting::SortMode, CurrentDir};
+    use std::{fs, path::Path, sync::Arc};
+
+    #[test]
+    fn mime_from_extension() {
+        assert_eq!(from_extension(Path::new("/a/b.png")), Some("image/png"));
+        assert_eq!(
+            from_extension(Path::new("README.MD")),
+            Some("text/markdown")
+        );
+    }
+
+    #[test]
+    fn mime_from_unknown_extension() {
+        assert_eq!(from_extension(Path::new("file.unknownext")), None);
+        assert_eq!(from_extension(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn mime_from_content() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00"), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff(b"hello"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn text_is_recognized() {
+        assert!(looks_like_text(b"plain ascii"));
+        assert!(looks_like_text("ääkköset".as_bytes()));
+        // The last character is cut in half
+        assert!(looks_like_text(&"ä".as_bytes()[..1]));
+        assert!(!looks_like_text(b"nul\x00byte"));
+        assert!(!looks_like_text(b"\xff\xfe\xfa invalid"));
+    }
+
+    #[test]
+    fn content_wins_over_extension() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::write(root.join("image.txt"), b"GIF89a\x01\x00").unwrap();
+        fs::write(root.join("document.docx"), b"PK\x03\x04rest").unwrap();
+        fs::write(root.join("notes"), "just some text").unwrap();
+
+        let detected = [
+            detect(&root.join("image.txt")),
+            detect(&root.join("document.docx")),
+            detect(&root.join("notes")),
+        ];
+
+        assert_eq!(detected[0], "image/gif");
+        assert!(detected[1].contains("wordprocessingml"));
+        assert_eq!(detected[2], "text/plain");
+    }
+
+    #[test]
+    fn cache_is_keyed_by_inode_and_mtime() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("mime_cache_test");
+        fs::write(&path, "%PDF-1.4").unwrap();
+        let metadata = fs::symlink_metadata(&path).unwrap();
+        let cache = MimeCache::default();
+
+        let first = cache.get(&path, &metadata);
+        // Same inode and mtime, so the stale content isn't read again
+        fs::write(&path, b"\x89PNG\r\n\x1a\n").unwrap();
+        let second = cache.get(&path, &metadata);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, "application/pdf");
+        assert_eq!(second, "application/pdf");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn special_files_are_not_opened() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        // Opening a FIFO without a writer would block the listing
+        nix::unistd::mkfifo(&root.join("pipe"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+        fs::write(root.join("notes"), "text").unwrap();
+
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let current_dir = CurrentDir::new(&root, cache).unwrap();
+        let mimes = current_dir
+            .get_siblings(&SortMode::default(), &ListingOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.name, file.mime.unwrap()))
+            .collect::<Vec<(String, String)>>();
+
+        assert!(mimes.contains(&("pipe".to_string(), "inode/fifo".to_string())));
+        assert!(mimes.contains(&("notes".to_string(), "text/plain".to_string())));
+        assert_eq!(detect(&root.join("pipe")), "inode/fifo");
+        assert_eq!(detect(Path::new("/dev/null")), "inode/chardevice");
+    }
+}
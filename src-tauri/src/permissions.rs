@@ -0,0 +1,383 @@
+use super::{platform, CurrentDirError};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const USER_BITS: u32 = 0o4700;
+const GROUP_BITS: u32 = 0o2070;
+const OTHER_BITS: u32 = 0o1007;
+const ALL_BITS: u32 = 0o7777;
+
+/// A mode given either in octal (`755`) or in the symbolic form of `chmod`
+/// (`u+x,go-w`, `a=rX`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Octal(u32),
+    Symbolic(Vec<SymbolicClause>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolicClause {
+    who: u32,
+    operations: Vec<(char, Permission)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Permission {
+    Bits {
+        bits: u32,
+        conditional_execute: bool,
+    },
+    /// Copies the permissions of the given class, eg. `g=u`
+    CopyOf(u32),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Ownership {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PermissionChange {
+    pub path: PathBuf,
+    pub old: Option<Ownership>,
+    pub new: Option<Ownership>,
+    pub error: Option<CurrentDirError>,
+}
+
+impl TryFrom<&str> for Mode {
+    type Error = CurrentDirError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || CurrentDirError::InvalidMode {
+            mode: value.to_owned(),
+        };
+
+        if !value.is_empty() && value.chars().all(|c| c.is_digit(8)) {
+            return u32::from_str_radix(value, 8)
+                .ok()
+                .filter(|mode| *mode <= ALL_BITS)
+                .map(Mode::Octal)
+                .ok_or_else(invalid);
+        }
+
+        let mut clauses = vec![];
+        for clause in value.split(',') {
+            let mut chars = clause.chars().peekable();
+            let mut who = 0;
+            while let Some(c) = chars.next_if(|c| "ugoa".contains(*c)) {
+                who |= class_bits(c);
+            }
+            if who == 0 {
+                who = ALL_BITS;
+            }
+
+            let mut operations = vec![];
+            while let Some(operator) = chars.next_if(|c| "+-=".contains(*c)) {
+                let permission = match chars.next_if(|c| "ugo".contains(*c)) {
+                    Some(class) => Permission::CopyOf(class_bits(class)),
+                    None => {
+                        let mut bits = 0;
+                        let mut conditional_execute = false;
+                        while let Some(c) = chars.next_if(|c| "rwxXst".contains(*c)) {
+                            match c {
+                                'r' => bits |= 0o444,
+                                'w' => bits |= 0o222,
+                                'x' => bits |= 0o111,
+                                'X' => conditional_execute = true,
+                                's' => bits |= 0o6000,
+                                _ => bits |= 0o1000,
+                            }
+                        }
+                        Permission::Bits {
+                            bits,
+                            conditional_execute,
+                        }
+                    }
+                };
+                operations.push((operator, permission));
+            }
+
+            if operations.is_empty() || chars.next().is_some() {
+                return Err(invalid());
+            }
+            clauses.push(SymbolicClause { who, operations });
+        }
+        Ok(Mode::Symbolic(clauses))
+    }
+}
+
+fn class_bits(class: char) -> u32 {
+    match class {
+        'u' => USER_BITS,
+        'g' => GROUP_BITS,
+        'o' => OTHER_BITS,
+        _ => ALL_BITS,
+    }
+}
+
+impl Mode {
+    /// Returns the permission bits `current` would have after applying this mode
+    pub fn apply(&self, current: u32, is_dir: bool) -> u32 {
+        let current = current & ALL_BITS;
+        let clauses = match self {
+            Mode::Octal(mode) => return *mode,
+            Mode::Symbolic(clauses) => clauses,
+        };
+
+        clauses.iter().fold(current, |mode, clause| {
+            clause
+                .operations
+                .iter()
+                .fold(mode, |mode, (operator, permission)| {
+                    let bits = match permission {
+                        Permission::Bits {
+                            bits,
+                            conditional_execute,
+                        } => {
+                            let execute = *conditional_execute && (is_dir || mode & 0o111 != 0);
+                            bits | if execute { 0o111 } else { 0 }
+                        }
+                        Permission::CopyOf(class) => {
+                            // Spread the rwx bits of the class to every class
+                            let rwx = match *class {
+                                USER_BITS => (mode >> 6) & 0o7,
+                                GROUP_BITS => (mode >> 3) & 0o7,
+                                _ => mode & 0o7,
+                            };
+                            rwx * 0o111
+                        }
+                    } & clause.who;
+
+                    match operator {
+                        '+' => mode | bits,
+                        '-' => mode & !bits,
+                        // The classes include their special bit, so `u=`
+                        // clears setuid as well
+                        _ => (mode & !clause.who) | bits,
+                    }
+                })
+        })
+    }
+}
+
+fn from_io_error(path: &Path, err: io::Error) -> CurrentDirError {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => CurrentDirError::PermissionDenied {
+            path: path.to_string_lossy().to_string(),
+        },
+        _ => CurrentDirError::CannotChangePermissions {
+            reason: err.to_string(),
+        },
+    }
+}
+
+/// Expands the given paths with their contents when `recursive` is set.
+/// Links found inside the folders aren't followed.
+fn collect_paths(paths: &[PathBuf], recursive: bool) -> Vec<PathBuf> {
+    if !recursive {
+        return paths.to_vec();
+    }
+    paths
+        .iter()
+        .flat_map(|path| {
+            walkdir::WalkDir::new(path)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .map(|entry| entry.into_path())
+        })
+        .collect()
+}
+
+fn change_each(
+    paths: &[PathBuf],
+    recursive: bool,
+    dry_run: bool,
+    change: impl Fn(&Path, Ownership, bool) -> Ownership,
+    apply: impl Fn(&Path, Ownership) -> io::Result<()>,
+) -> Vec<PermissionChange> {
+    collect_paths(paths, recursive)
+        .into_iter()
+        .map(|path| {
+            // Systems without Unix modes and owners have nothing to change
+            let read = fs::symlink_metadata(&path).and_then(|metadata| {
+                match (platform::mode(&metadata), platform::owner(&metadata)) {
+                    (Some(mode), Some(owner)) => Ok((metadata.is_dir(), mode, owner)),
+                    _ => Err(io::Error::from(io::ErrorKind::Unsupported)),
+                }
+            });
+            let (is_dir, mode, (uid, gid)) = match read {
+                Ok(found) => found,
+                Err(err) => {
+                    return PermissionChange {
+                        error: Some(from_io_error(&path, err)),
+                        path,
+                        old: None,
+                        new: None,
+                    }
+                }
+            };
+            let old = Ownership {
+                mode: mode & ALL_BITS,
+                uid,
+                gid,
+            };
+            let new = change(&path, old, is_dir);
+            let error = if dry_run || old == new {
+                None
+            } else {
+                apply(&path, new).err().map(|err| from_io_error(&path, err))
+            };
+
+            PermissionChange {
+                path,
+                old: Some(old),
+                new: Some(new),
+                error,
+            }
+        })
+        .collect()
+}
+
+pub fn change_mode(
+    paths: &[PathBuf],
+    mode: &Mode,
+    recursive: bool,
+    dry_run: bool,
+) -> Vec<PermissionChange> {
+    change_each(
+        paths,
+        recursive,
+        dry_run,
+        |path, old, is_dir| Ownership {
+            // Links don't have permissions of their own
+            mode: if path.is_symlink() {
+                old.mode
+            } else {
+                mode.apply(old.mode, is_dir)
+            },
+            ..old
+        },
+        |path, new| set_mode(path, new.mode),
+    )
+}
+
+pub fn change_owner(
+    paths: &[PathBuf],
+    uid: Option<u32>,
+    gid: Option<u32>,
+    recursive: bool,
+    dry_run: bool,
+) -> Vec<PermissionChange> {
+    change_each(
+        paths,
+        recursive,
+        dry_run,
+        |_, old, _| Ownership {
+            uid: uid.unwrap_or(old.uid),
+            gid: gid.unwrap_or(old.gid),
+            ..old
+        },
+        |path, new| set_owner(path, new.uid, new.gid),
+    )
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(unix)]
+fn set_owner(path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))
+}
+
+/// Other systems have no modes or owners to read, so changes stop before
+/// these
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(not(unix))]
+fn set_owner(_path: &Path, _uid: u32, _gid: u32) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{change_mode, Mode};
+    #[cfg(unix)]
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    #[test]
+    fn octal_mode_is_parsed() {
+        assert_eq!(Mode::try_from("755").unwrap(), Mode::Octal(0o755));
+        assert_eq!(Mode::try_from("4750").unwrap(), Mode::Octal(0o4750));
+        assert!(Mode::try_from("77777").is_err());
+    }
+
+    #[test]
+    fn garbage_mode_is_rejected() {
+        assert!(Mode::try_from("").is_err());
+        assert!(Mode::try_from("u").is_err());
+        assert!(Mode::try_from("u+q").is_err());
+        assert!(Mode::try_from("z+x").is_err());
+        assert!(Mode::try_from("u+x,").is_err());
+    }
+
+    #[test]
+    fn symbolic_mode_is_applied() {
+        let apply = |mode: &str, current: u32| Mode::try_from(mode).unwrap().apply(current, false);
+
+        assert_eq!(apply("u+x", 0o644), 0o744);
+        assert_eq!(apply("go-w", 0o666), 0o644);
+        assert_eq!(apply("a=r", 0o777), 0o444);
+        assert_eq!(apply("+x", 0o644), 0o755);
+        assert_eq!(apply("u=rwx,g=rx,o=", 0o000), 0o750);
+        assert_eq!(apply("g=u", 0o640), 0o660);
+        assert_eq!(apply("u+s", 0o755), 0o4755);
+        assert_eq!(apply("+t", 0o777), 0o1777);
+        assert_eq!(apply("u=rw", 0o4755), 0o655);
+        assert_eq!(apply("g=rx", 0o2775), 0o755);
+        assert_eq!(apply("o=rwx", 0o1770), 0o777);
+        assert_eq!(apply("a=r", 0o7777), 0o444);
+    }
+
+    #[test]
+    fn conditional_execute_is_applied() {
+        let mode = Mode::try_from("a+X").unwrap();
+
+        assert_eq!(mode.apply(0o644, false), 0o644);
+        assert_eq!(mode.apply(0o744, false), 0o755);
+        assert_eq!(mode.apply(0o644, true), 0o755);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dry_run_does_not_change_mode() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("dry_run_chmod_test");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let paths = vec![path.clone()];
+
+        let dry = change_mode(&paths, &Mode::Octal(0o600), false, true);
+        let mode_after_dry_run = fs::metadata(&path).unwrap().permissions().mode();
+        let real = change_mode(&paths, &Mode::Octal(0o600), false, false);
+        let mode_after_real_run = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(dry[0].new.unwrap().mode, 0o600);
+        assert_eq!(mode_after_dry_run & 0o777, 0o644);
+        assert!(real[0].error.is_none());
+        assert_eq!(mode_after_real_run & 0o777, 0o600);
+    }
+}
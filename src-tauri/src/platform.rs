@@ -0,0 +1,153 @@
+//! File details that only Unix keeps. Other systems get fallbacks so that
+//! the rest of the app doesn't need to care where it runs.
+
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// Seconds since the unix epoch, negative before it
+fn seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+/// Seconds since the unix epoch
+#[cfg(unix)]
+pub fn modified(metadata: &fs::Metadata) -> i64 {
+    metadata.mtime()
+}
+
+#[cfg(not(unix))]
+pub fn modified(metadata: &fs::Metadata) -> i64 {
+    metadata.modified().map_or(0, seconds)
+}
+
+/// Nanoseconds since the unix epoch, for telling apart changes made within
+/// the same second
+#[cfg(unix)]
+pub fn modified_nanos(metadata: &fs::Metadata) -> i64 {
+    metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec()
+}
+
+#[cfg(not(unix))]
+pub fn modified_nanos(metadata: &fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as i64)
+}
+
+pub fn accessed(metadata: &fs::Metadata) -> Option<i64> {
+    metadata.accessed().ok().map(seconds)
+}
+
+/// When the metadata, rather than the contents, last changed
+#[cfg(unix)]
+pub fn changed(metadata: &fs::Metadata) -> Option<i64> {
+    Some(metadata.ctime())
+}
+
+#[cfg(not(unix))]
+pub fn changed(_metadata: &fs::Metadata) -> Option<i64> {
+    None
+}
+
+pub fn created(metadata: &fs::Metadata) -> Option<i64> {
+    metadata.created().ok().map(seconds)
+}
+
+/// The device and the inode, which together tell whether two paths are the
+/// same file
+#[cfg(unix)]
+pub fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+pub fn inode(metadata: &fs::Metadata) -> Option<u64> {
+    file_id(metadata).map(|(_, inode)| inode)
+}
+
+pub fn device(metadata: &fs::Metadata) -> Option<u64> {
+    file_id(metadata).map(|(device, _)| device)
+}
+
+/// Number of hard links to the file
+#[cfg(unix)]
+pub fn links(metadata: &fs::Metadata) -> Option<u64> {
+    Some(metadata.nlink())
+}
+
+#[cfg(not(unix))]
+pub fn links(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Space taken on the disk. Without block counts this is the length.
+#[cfg(unix)]
+pub fn allocated(metadata: &fs::Metadata) -> u64 {
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+pub fn allocated(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// The file type and permission bits
+#[cfg(unix)]
+pub fn mode(metadata: &fs::Metadata) -> Option<u32> {
+    Some(metadata.mode())
+}
+
+#[cfg(not(unix))]
+pub fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+pub fn owner(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    Some((metadata.uid(), metadata.gid()))
+}
+
+#[cfg(not(unix))]
+pub fn owner(_metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{modified, modified_nanos, seconds};
+    use std::{
+        fs,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn times_before_the_epoch_are_negative() {
+        assert_eq!(seconds(UNIX_EPOCH + Duration::from_secs(5)), 5);
+        assert_eq!(seconds(UNIX_EPOCH - Duration::from_secs(5)), -5);
+    }
+
+    #[test]
+    fn nanoseconds_agree_with_seconds() {
+        let temp = tempfile::tempdir().unwrap();
+        let metadata = fs::metadata(temp.path()).unwrap();
+
+        assert_eq!(
+            modified_nanos(&metadata) / 1_000_000_000,
+            modified(&metadata)
+        );
+    }
+}
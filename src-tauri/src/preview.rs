@@ -0,0 +1,271 @@
+use super::{mime, CurrentDirError};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_PREVIEW_KB: usize = 64;
+/// Larger previews are cut to this, the preview is kept in memory
+const MAX_PREVIEW_KB: usize = 16 * 1024;
+const HEX_BYTES_PER_LINE: usize = 16;
+
+const LANGUAGES: &[(&str, &str)] = &[
+    ("bash", "bash"),
+    ("c", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("cs", "csharp"),
+    ("css", "css"),
+    ("go", "go"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("htm", "html"),
+    ("html", "html"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("json", "json"),
+    ("kt", "kotlin"),
+    ("lua", "lua"),
+    ("md", "markdown"),
+    ("php", "php"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("scss", "scss"),
+    ("sh", "bash"),
+    ("sql", "sql"),
+    ("svelte", "svelte"),
+    ("toml", "toml"),
+    ("ts", "typescript"),
+    ("xml", "xml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("zsh", "bash"),
+];
+
+const LANGUAGES_BY_NAME: &[(&str, &str)] = &[
+    ("CMakeLists.txt", "cmake"),
+    ("Dockerfile", "dockerfile"),
+    ("Makefile", "makefile"),
+    ("PKGBUILD", "bash"),
+];
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum PreviewContent {
+    Text {
+        text: String,
+        encoding: String,
+        language: Option<String>,
+    },
+    Hex {
+        dump: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FilePreview {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Whether only the beginning of the file is included
+    pub truncated: bool,
+    pub content: PreviewContent,
+}
+
+pub fn detect_language(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    if let Some((_, language)) = LANGUAGES_BY_NAME.iter().find(|(n, _)| *n == name) {
+        return Some(language);
+    }
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, language)| *language)
+}
+
+/// Returns the decoded text and the name of its encoding, or `None` if the
+/// bytes look binary
+pub fn decode_text(bytes: &[u8]) -> Option<(String, &'static str)> {
+    if let Some(rest) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        return Some((String::from_utf8_lossy(rest).to_string(), "UTF-8"));
+    }
+    let utf16 = |rest: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units = rest
+            .chunks_exact(2)
+            .map(|pair| from_bytes([pair[0], pair[1]]))
+            .collect::<Vec<u16>>();
+        String::from_utf16_lossy(&units)
+    };
+    if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        return Some((utf16(rest, u16::from_le_bytes), "UTF-16LE"));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        return Some((utf16(rest, u16::from_be_bytes), "UTF-16BE"));
+    }
+
+    if mime::looks_like_text(bytes) {
+        return Some((String::from_utf8_lossy(bytes).to_string(), "UTF-8"));
+    }
+
+    // Legacy 8-bit text still consists mostly of printable characters
+    let printable = bytes
+        .iter()
+        .filter(|b| b.is_ascii_graphic() || b.is_ascii_whitespace() || **b >= 0xa0)
+        .count();
+    if !bytes.contains(&0) && printable * 100 >= bytes.len() * 95 {
+        return Some((bytes.iter().map(|b| *b as char).collect(), "ISO-8859-1"));
+    }
+    None
+}
+
+/// Formats the bytes like `hexdump -C`
+pub fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(HEX_BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, line)| {
+            let hex = line
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>()
+                .join(" ");
+            let ascii = line
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            format!(
+                "{:08x}  {:<width$}  |{}|\n",
+                i * HEX_BYTES_PER_LINE,
+                hex,
+                ascii,
+                width = HEX_BYTES_PER_LINE * 3 - 1
+            )
+        })
+        .collect()
+}
+
+/// Reads at most `max_kb` kilobytes from the beginning of the file, up to
+/// `MAX_PREVIEW_KB`
+pub fn preview_file(path: &Path, max_kb: usize) -> Result<FilePreview, CurrentDirError> {
+    let path_string = || path.to_string_lossy().to_string();
+    let metadata = fs::metadata(path).map_err(|_| CurrentDirError::CannotReadMetadata {
+        path: path_string(),
+    })?;
+    // Reading a device or a FIFO could block forever or never end, and
+    // neither of them counts as a regular file
+    if !metadata.is_file() {
+        return Err(CurrentDirError::NotARegularFile {
+            path: path_string(),
+        });
+    }
+
+    let limit = max_kb.min(MAX_PREVIEW_KB).saturating_mul(1024);
+    let mut bytes = Vec::with_capacity(limit.min(metadata.len() as usize));
+    fs::File::open(path)
+        .and_then(|file| file.take(limit as u64).read_to_end(&mut bytes))
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::PermissionDenied => CurrentDirError::PermissionDenied {
+                path: path_string(),
+            },
+            _ => CurrentDirError::CannotReadFile {
+                reason: err.to_string(),
+            },
+        })?;
+
+    let content = match decode_text(&bytes) {
+        Some((text, encoding)) => PreviewContent::Text {
+            text,
+            encoding: encoding.to_owned(),
+            language: detect_language(path).map(str::to_owned),
+        },
+        None => PreviewContent::Hex {
+            dump: hex_dump(&bytes),
+        },
+    };
+
+    Ok(FilePreview {
+        path: path.to_owned(),
+        size: metadata.len(),
+        truncated: (bytes.len() as u64) < metadata.len(),
+        content,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_text, detect_language, hex_dump, preview_file, PreviewContent};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn language_is_detected() {
+        assert_eq!(detect_language(Path::new("src/main.rs")), Some("rust"));
+        assert_eq!(detect_language(Path::new("/a/Makefile")), Some("makefile"));
+        assert_eq!(detect_language(Path::new("notes.txt")), None);
+    }
+
+    #[test]
+    fn encodings_are_detected() {
+        assert_eq!(
+            decode_text("ääkkönen".as_bytes()),
+            Some((String::from("ääkkönen"), "UTF-8"))
+        );
+        assert_eq!(
+            decode_text(b"\xff\xfeh\x00i\x00"),
+            Some((String::from("hi"), "UTF-16LE"))
+        );
+        assert_eq!(
+            decode_text(b"\xfe\xff\x00h\x00i"),
+            Some((String::from("hi"), "UTF-16BE"))
+        );
+        assert_eq!(
+            decode_text(b"p\xe4iv\xe4\xe4"),
+            Some((String::from("päivää"), "ISO-8859-1"))
+        );
+        assert_eq!(decode_text(b"\x00\x01\x02\x03"), None);
+    }
+
+    #[test]
+    fn hex_dump_is_formatted() {
+        assert_eq!(
+            hex_dump(b"Hello\x00"),
+            format!("00000000  48 65 6c 6c 6f 00{}  |Hello.|\n", " ".repeat(30))
+        );
+    }
+
+    #[test]
+    fn preview_is_truncated() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("preview_test.rs");
+        fs::write(&path, "fn main() {}\n".repeat(200)).unwrap();
+
+        let preview = preview_file(&path, 1).unwrap();
+        let whole = preview_file(&path, usize::MAX).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(preview.truncated);
+        assert!(!whole.truncated);
+        match preview.content {
+            PreviewContent::Text { text, language, .. } => {
+                assert_eq!(text.len(), 1024);
+                assert_eq!(language, Some(String::from("rust")));
+            }
+            PreviewContent::Hex { .. } => panic!("Text was previewed as hex"),
+        }
+    }
+
+    #[test]
+    fn devices_are_refused() {
+        assert!(preview_file(Path::new("/dev/null"), 1).is_err());
+        assert!(preview_file(Path::new("/"), 1).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use super::{
+    jobs::{Job, JobId},
+    mime, platform, users, CurrentDirError, FileType,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// How many entries are walked between two progress reports of [`folder_size`]
+const PROGRESS_INTERVAL: u64 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FileProperties {
+    pub name: String,
+    pub path: PathBuf,
+    pub filetype: FileType,
+    pub size: u64,
+    /// Bytes allocated on the disk
+    pub allocated: u64,
+    /// Timestamps are in seconds since the unix epoch
+    pub accessed: Option<i64>,
+    pub modified: i64,
+    pub changed: Option<i64>,
+    pub created: Option<i64>,
+    /// The Unix only details are `None` on other systems
+    pub mode: Option<u32>,
+    pub permissions: Option<String>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub inode: Option<u64>,
+    pub links: Option<u64>,
+    pub mime: String,
+    pub symlink_target: Option<PathBuf>,
+    /// The job counting what is inside a folder, its results arrive as
+    /// [`FolderSize`] reports
+    pub size_job: Option<JobId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct FolderSize {
+    pub job_id: JobId,
+    pub path: PathBuf,
+    pub size: u64,
+    pub files: u64,
+    pub folders: u64,
+    pub links: u64,
+    pub done: bool,
+}
+
+pub fn get_file_properties(path: &Path) -> Result<FileProperties, CurrentDirError> {
+    let metadata = fs::symlink_metadata(path).map_err(|_| CurrentDirError::CannotReadMetadata {
+        path: path.to_string_lossy().to_string(),
+    })?;
+    let filetype = FileType::from(metadata.file_type());
+    let mime = match filetype {
+        FileType::Folder => mime::DIRECTORY,
+        FileType::Link => mime::SYMLINK,
+        FileType::File => mime::detect(path),
+    };
+    let mode = platform::mode(&metadata);
+    let owner = platform::owner(&metadata);
+
+    Ok(FileProperties {
+        name: path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .to_string(),
+        path: path.to_owned(),
+        filetype,
+        size: metadata.len(),
+        allocated: platform::allocated(&metadata),
+        accessed: platform::accessed(&metadata),
+        modified: platform::modified(&metadata),
+        changed: platform::changed(&metadata),
+        created: platform::created(&metadata),
+        mode,
+        permissions: mode.map(format_mode),
+        uid: owner.map(|(uid, _)| uid),
+        gid: owner.map(|(_, gid)| gid),
+        owner: owner.and_then(|(uid, _)| users::user_name(uid)),
+        group: owner.and_then(|(_, gid)| users::group_name(gid)),
+        inode: platform::inode(&metadata),
+        links: platform::links(&metadata),
+        mime: mime.to_owned(),
+        symlink_target: fs::read_link(path).ok(),
+        size_job: None,
+    })
+}
+
+/// Renders the permission bits of a mode like `ls -l` does, eg. `rwxr-xr-x`
+pub fn format_mode(mode: u32) -> String {
+    let special = [(0o4000, 's', 'S'), (0o2000, 's', 'S'), (0o1000, 't', 'T')];
+    let mut rendered = String::with_capacity(9);
+
+    for (i, (special_bit, set, unset)) in special.into_iter().enumerate() {
+        let bits = (mode >> (6 - i * 3)) & 0o7;
+        rendered.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        rendered.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        rendered.push(match (bits & 0o1 != 0, mode & special_bit != 0) {
+            (true, true) => set,
+            (false, true) => unset,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    rendered
+}
+
+/// Walks the folder recursively without following links. `on_progress` is
+/// called periodically with the partial result and once more when done.
+pub fn folder_size(
+    path: &Path,
+    job: &Job,
+    mut on_progress: impl FnMut(&FolderSize),
+) -> Result<FolderSize, CurrentDirError> {
+    let mut result = FolderSize {
+        job_id: job.id,
+        path: path.to_owned(),
+        ..Default::default()
+    };
+
+    let mut since_last_report = 0;
+
+    for entry in walkdir::WalkDir::new(path)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        if job.is_cancelled() {
+            return Err(CurrentDirError::Cancelled);
+        }
+        match FileType::from(entry.file_type()) {
+            FileType::Folder => result.folders += 1,
+            FileType::File => result.files += 1,
+            FileType::Link => result.links += 1,
+        }
+        result.size += entry.metadata().map(|m| m.len()).unwrap_or(0);
+
+        since_last_report += 1;
+        if since_last_report == PROGRESS_INTERVAL {
+            since_last_report = 0;
+            on_progress(&result);
+        }
+    }
+
+    result.done = true;
+    on_progress(&result);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{folder_size, format_mode};
+    use crate::jobs::Job;
+    use std::fs;
+
+    #[test]
+    fn mode_is_formatted() {
+        assert_eq!(format_mode(0o755), "rwxr-xr-x");
+        assert_eq!(format_mode(0o100644), "rw-r--r--");
+        assert_eq!(format_mode(0o000), "---------");
+    }
+
+    #[test]
+    fn special_bits_are_formatted() {
+        assert_eq!(format_mode(0o4755), "rwsr-xr-x");
+        assert_eq!(format_mode(0o2745), "rwxr-Sr-x");
+        assert_eq!(format_mode(0o1777), "rwxrwxrwt");
+        assert_eq!(format_mode(0o1776), "rwxrwxrwT");
+    }
+
+    #[test]
+    fn folder_size_counts_recursively() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("inner")).unwrap();
+        fs::write(root.join("a.txt"), "12345").unwrap();
+        fs::write(root.join("inner").join("b.txt"), "123").unwrap();
+
+        let mut reports = 0;
+        let result = folder_size(&root, &Job::detached(), |_| reports += 1).unwrap();
+
+        assert!(result.done);
+        assert_eq!(result.files, 2);
+        assert_eq!(result.folders, 1);
+        assert!(result.size >= 8);
+        assert_eq!(reports, 1);
+    }
+}
@@ -0,0 +1,283 @@
+use super::{
+    mime::SpecialFile,
+    vfs::{OnChanges, RemoteUrl, VfsBackend, VfsEntry, VfsMetadata, VfsWatcher},
+    FileType,
+};
+use ssh2::{CheckResult, KnownHostFileKind, OpenFlags, OpenType, Session, Sftp};
+use std::{
+    env, fmt,
+    io::{self, Read},
+    net::TcpStream,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_PORT: u16 = 22;
+/// Calls that get no answer in this time fail instead of hanging the tab
+const TIMEOUT_MS: u32 = 30_000;
+/// Tried in this order when the agent has no key that the server accepts
+const KEY_FILES: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+fn ssh_folder() -> PathBuf {
+    PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".ssh")
+}
+
+fn permission_denied(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, reason)
+}
+
+/// Only servers that are already in `~/.ssh/known_hosts` are trusted, so
+/// new servers have to be added by connecting once with `ssh`
+fn check_host_key(session: &Session, host: &str, port: u16) -> io::Result<()> {
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| io::Error::other(format!("{} sent no host key", host)))?;
+    let mut known_hosts = session.known_hosts()?;
+    let _ = known_hosts.read_file(
+        &ssh_folder().join("known_hosts"),
+        KnownHostFileKind::OpenSSH,
+    );
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(permission_denied(format!(
+            "the host key of {} doesn't match the one in known_hosts",
+            host
+        ))),
+        _ => Err(permission_denied(format!(
+            "{} isn't in known_hosts, connect to it once with ssh first",
+            host
+        ))),
+    }
+}
+
+/// Keys with a passphrase only work through the agent
+fn authenticate(session: &Session, user: &str) -> io::Result<()> {
+    if session.userauth_agent(user).is_ok() {
+        return Ok(());
+    }
+    for name in KEY_FILES {
+        let key = ssh_folder().join(name);
+        if key.is_file() && session.userauth_pubkey_file(user, None, &key, None).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(permission_denied(format!(
+        "no key from the agent or {} was accepted for {}",
+        ssh_folder().display(),
+        user
+    )))
+}
+
+fn metadata(stat: &ssh2::FileStat) -> VfsMetadata {
+    let filetype = match stat.file_type() {
+        ssh2::FileType::Directory => FileType::Folder,
+        ssh2::FileType::Symlink => FileType::Link,
+        _ => FileType::File,
+    };
+    let special = match stat.file_type() {
+        ssh2::FileType::NamedPipe => Some(SpecialFile::Fifo),
+        ssh2::FileType::Socket => Some(SpecialFile::Socket),
+        ssh2::FileType::CharDevice => Some(SpecialFile::CharDevice),
+        ssh2::FileType::BlockDevice => Some(SpecialFile::BlockDevice),
+        _ => None,
+    };
+    VfsMetadata {
+        filetype,
+        size: stat.size.unwrap_or(0),
+        modified: stat.mtime.unwrap_or(0) as i64,
+        inode: 0,
+        special,
+    }
+}
+
+/// A folder tree on a server that is reached over SSH, with paths such as
+/// `sftp://user@host/home/user`
+pub struct SftpBackend {
+    url: RemoteUrl,
+    sftp: Sftp,
+    // The sftp channel runs over this session
+    _session: Session,
+}
+
+impl fmt::Debug for SftpBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SftpBackend")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SftpBackend {
+    /// Logs in with the agent or the default keys. The user defaults to the
+    /// local user like with `ssh`.
+    pub fn connect(url: &RemoteUrl) -> io::Result<Self> {
+        let port = url.port.unwrap_or(DEFAULT_PORT);
+        let user = match &url.user {
+            Some(user) => user.clone(),
+            None => env::var("USER").map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "no user was given in the url")
+            })?,
+        };
+        let host = url.host.trim_start_matches('[').trim_end_matches(']');
+
+        let mut session = Session::new()?;
+        session.set_timeout(TIMEOUT_MS);
+        session.set_tcp_stream(TcpStream::connect((host, port))?);
+        session.handshake()?;
+        check_host_key(&session, &url.host, port)?;
+        authenticate(&session, &user)?;
+
+        Ok(SftpBackend {
+            url: url.clone(),
+            sftp: session.sftp()?,
+            _session: session,
+        })
+    }
+
+    fn inner(&self, path: &Path) -> io::Result<PathBuf> {
+        self.url.inner(path)
+    }
+}
+
+impl VfsBackend for SftpBackend {
+    /// A url without a path starts from the home folder of the user
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let inner = self.inner(path)?;
+        let inner = match inner.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => inner,
+        };
+        Ok(self.url.join(&self.sftp.realpath(&inner)?))
+    }
+
+    fn list(&self, folder: &Path) -> io::Result<Vec<VfsEntry>> {
+        Ok(self
+            .sftp
+            .readdir(self.inner(folder)?)?
+            .into_iter()
+            .map(|(path, stat)| VfsEntry {
+                path: self.url.join(&path),
+                metadata: metadata(&stat),
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        Ok(metadata(&self.sftp.lstat(&self.inner(path)?)?))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(self.sftp.open(self.inner(path)?)?))
+    }
+
+    fn create(&self, path: &Path, filetype: FileType) -> io::Result<()> {
+        let inner = self.inner(path)?;
+        match filetype {
+            FileType::Folder => Ok(self.sftp.mkdir(&inner, 0o755)?),
+            FileType::File => {
+                self.sftp.open_mode(
+                    &inner,
+                    OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+                    0o644,
+                    OpenType::File,
+                )?;
+                Ok(())
+            }
+            FileType::Link => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "links can't be created without a target",
+            )),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let inner = self.inner(path)?;
+        match self.sftp.lstat(&inner)?.is_dir() {
+            true => Ok(self.sftp.rmdir(&inner)?),
+            false => Ok(self.sftp.unlink(&inner)?),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let target = self.inner(to)?;
+        if self.sftp.lstat(&target).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ));
+        }
+        Ok(self.sftp.rename(&self.inner(from)?, &target, None)?)
+    }
+
+    fn store(&self, path: &Path, contents: &mut dyn Read) -> io::Result<u64> {
+        let mut file = self.sftp.create(&self.inner(path)?)?;
+        io::copy(contents, &mut file)
+    }
+
+    /// SFTP has no change notifications, so remote folders aren't watched
+    fn watch(&self, _folder: &Path, _on_changes: OnChanges) -> io::Result<Box<dyn VfsWatcher>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} can't be watched", self.url),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SftpBackend;
+    use crate::{
+        vfs::{copy, RemoteUrl, VfsBackend},
+        FileType,
+    };
+    use std::{env, fs, io::Read, path::Path};
+
+    /// Needs a server, for example
+    /// `docker run -d -p 2222:2222 -e PUBLIC_KEY="$(cat ~/.ssh/id_ed25519.pub)" -e USER_NAME=test lscr.io/linuxserver/openssh-server`,
+    /// added to known_hosts with `ssh -p 2222 test@localhost true`, and
+    /// `MIELIKKI_SFTP_URL=sftp://test@localhost:2222/config`
+    #[test]
+    #[ignore]
+    fn files_are_managed_over_sftp() {
+        let remote = env::var("MIELIKKI_SFTP_URL").unwrap();
+        let (url, _) = RemoteUrl::parse(Path::new(&remote)).unwrap();
+        let backend = SftpBackend::connect(&url).unwrap();
+        let root = backend
+            .canonicalize(Path::new(&remote))
+            .unwrap()
+            .join("mielikki_sftp_test");
+        let local_temp = tempfile::tempdir().unwrap();
+        let local = local_temp.path().to_owned();
+        fs::write(local.join("notes.txt"), "hello").unwrap();
+        let local_backend = crate::vfs::LocalBackend::default();
+
+        backend.create(&root, FileType::Folder).unwrap();
+        copy(
+            &local_backend,
+            &local.join("notes.txt"),
+            &backend,
+            &root.join("notes.txt"),
+        )
+        .unwrap();
+        backend
+            .rename(&root.join("notes.txt"), &root.join("renamed.txt"))
+            .unwrap();
+        let names = backend
+            .list(&root)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+        let mut contents = String::new();
+        backend
+            .open(&root.join("renamed.txt"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        backend.remove(&root.join("renamed.txt")).unwrap();
+        backend.remove(&root).unwrap();
+
+        assert_eq!(names, vec![root.join("renamed.txt")]);
+        assert_eq!(contents, "hello");
+        assert!(backend.stat(&root).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use super::{listing::ListingOptions, sorting::SortMode, vfs::VfsBackend, FileData};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderWindow {
+    pub name: String,
+    /// Number of entries in the whole listing
+    pub total: usize,
+    pub offset: usize,
+    pub files: Vec<FileData>,
+    pub is_at_root: bool,
+}
+
+/// Modification time of a folder, which changes whenever entries are added,
+/// removed or renamed inside it. Backends only keep whole seconds, so changes
+/// within the same second are left for the folder watcher to report.
+fn folder_modified(backend: &dyn VfsBackend, path: &Path) -> Option<i64> {
+    backend.stat(path).ok().map(|metadata| metadata.modified)
+}
+
+/// A sorted listing of one folder that windows are served from. It is
+/// rebuilt when the folder, the sort mode or the listing options change.
+#[derive(Debug)]
+pub struct Snapshot {
+    path: PathBuf,
+    sort_mode: SortMode,
+    options: ListingOptions,
+    modified: Option<i64>,
+    files: Vec<FileData>,
+}
+
+impl Snapshot {
+    pub fn new(
+        backend: &dyn VfsBackend,
+        path: &Path,
+        sort_mode: &SortMode,
+        options: &ListingOptions,
+        mut files: Vec<FileData>,
+    ) -> Self {
+        sort_mode.sort(&mut files);
+        Snapshot {
+            path: path.to_owned(),
+            sort_mode: *sort_mode,
+            options: options.clone(),
+            modified: folder_modified(backend, path),
+            files,
+        }
+    }
+
+    /// Changes to the contents of files don't touch the folder, so those have
+    /// to be reported by invalidating the snapshot
+    pub fn is_valid_for(
+        &self,
+        backend: &dyn VfsBackend,
+        path: &Path,
+        sort_mode: &SortMode,
+        options: &ListingOptions,
+    ) -> bool {
+        self.path == path
+            && self.sort_mode == *sort_mode
+            && self.options == *options
+            && self.modified.is_some()
+            && self.modified == folder_modified(backend, path)
+    }
+
+    pub fn total(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Out of range windows are cut to the end of the listing
+    pub fn window_mut(&mut self, offset: usize, limit: usize) -> &mut [FileData] {
+        let start = offset.min(self.files.len());
+        let end = start.saturating_add(limit).min(self.files.len());
+        &mut self.files[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Snapshot;
+    use crate::{
+        filecache::FileCache, listing::ListingOptions, sorting::SortMode, vfs::LocalBackend,
+        CurrentDir, FileData, FileType,
+    };
+    use std::{fs, path::Path, sync::Arc};
+
+    #[test]
+    fn windows_are_cut_to_the_listing() {
+        let files = ["c", "a", "b"]
+            .iter()
+            .map(|name| FileData::new(name, &Path::new("/").join(name), FileType::File))
+            .collect();
+        let mut snapshot = Snapshot::new(
+            &LocalBackend::default(),
+            Path::new("/"),
+            &SortMode::default(),
+            &ListingOptions::default(),
+            files,
+        );
+
+        let names = |files: &mut [FileData]| {
+            files
+                .iter()
+                .map(|file| file.name.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(snapshot.total(), 3);
+        assert_eq!(names(snapshot.window_mut(1, 5)), vec!["b", "c"]);
+        assert!(snapshot.window_mut(10, usize::MAX).is_empty());
+    }
+
+    #[tokio::test]
+    async fn snapshot_follows_folder_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        for name in ["a", "b", "c"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+
+        let cache = Arc::new(FileCache::create_secondary().await);
+        let mut current_dir = CurrentDir::new(&root, cache).unwrap();
+        let sort_mode = SortMode::default();
+        let options = ListingOptions::default();
+        let first = current_dir
+            .get_folder_window(&sort_mode, &options, 0, 2)
+            .unwrap();
+        fs::remove_file(root.join("a")).unwrap();
+        current_dir.invalidate_snapshot();
+        let second = current_dir
+            .get_folder_window(&sort_mode, &options, 0, 2)
+            .unwrap();
+
+        assert_eq!(first.total, 3);
+        assert_eq!(first.files[0].name, "a");
+        assert!(first.files[0].mime.is_some());
+        assert_eq!(second.total, 2);
+        assert_eq!(second.files[0].name, "b");
+    }
+}
@@ -0,0 +1,284 @@
+use super::{database::AppDatabase, mime, CurrentDirError, FileData, FileType};
+use icu_collator::{Collator, CollatorOptions, Numeric};
+use icu_locid::Locale;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, env, path::Path};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+    Extension,
+    /// By the kind of entry and then by the MIME type its extension implies,
+    /// so that sorting doesn't have to read every file
+    Type,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SortMode {
+    pub key: SortKey,
+    pub descending: bool,
+    /// Folders stay on top regardless of the direction
+    pub folders_first: bool,
+}
+
+impl Default for SortMode {
+    /// Folders first, then the files grouped by their type
+    fn default() -> Self {
+        SortMode {
+            key: SortKey::Type,
+            descending: false,
+            folders_first: true,
+        }
+    }
+}
+
+thread_local! {
+    /// Loading the collation rules takes a while, so every thread that sorts
+    /// keeps its own collator
+    static COLLATOR: Option<Collator> = collator(&system_locale());
+}
+
+/// Numbers in names are compared by their value
+fn collator(locale: &Locale) -> Option<Collator> {
+    let mut options = CollatorOptions::new();
+    options.numeric = Some(Numeric::On);
+    Collator::try_new(&locale.into(), options).ok()
+}
+
+/// Turns a POSIX locale like `fi_FI.UTF-8@euro` into `fi-FI`
+fn parse_locale(posix: &str) -> Option<Locale> {
+    let name = posix.split(['.', '@']).next()?;
+    name.replace('_', "-").parse().ok()
+}
+
+/// Read from the same variables as `ls` does. `C` and unknown locales sort
+/// by the language neutral order.
+fn system_locale() -> Locale {
+    ["LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| parse_locale(&value))
+        .unwrap_or(Locale::UND)
+}
+
+/// Compares names in the collation order of the system locale, so that
+/// accented letters sort where the user expects them and `file2` comes
+/// before `file10`. Names that collate the same, like ones differing only by
+/// leading zeros or normalization, are ordered by their bytes.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    COLLATOR
+        .with(|collator| match collator {
+            Some(collator) => collator.compare(a, b),
+            None => Ordering::Equal,
+        })
+        .then_with(|| a.cmp(b))
+}
+
+fn extension(file: &FileData) -> Option<String> {
+    file.path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+impl SortMode {
+    pub fn compare(&self, a: &FileData, b: &FileData) -> Ordering {
+        let folders = if self.folders_first {
+            (b.filetype == FileType::Folder).cmp(&(a.filetype == FileType::Folder))
+        } else {
+            Ordering::Equal
+        };
+
+        let ordering = match self.key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+            SortKey::Extension => extension(a).cmp(&extension(b)),
+            SortKey::Type => a
+                .filetype
+                .cmp(&b.filetype)
+                .then_with(|| mime::from_extension(&a.path).cmp(&mime::from_extension(&b.path))),
+        }
+        .then_with(|| natural_cmp(&a.name, &b.name));
+
+        folders
+            .then(if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            })
+            .then_with(|| a.path.cmp(&b.path))
+    }
+
+    pub fn sort(&self, files: &mut [FileData]) {
+        files.sort_unstable_by(|a, b| self.compare(a, b));
+    }
+}
+
+impl AppDatabase {
+    /// Folders without a remembered mode use the default one
+    pub async fn get_sort_mode(&self, folder: &Path) -> Result<SortMode, CurrentDirError> {
+        let folder = folder.to_string_lossy().to_string();
+        let stored = self
+            .connection
+            .call(move |conn| {
+                conn.query_row(
+                    "SELECT mode FROM sort_modes WHERE path = ?1",
+                    [folder],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+            })
+            .await?;
+
+        Ok(stored
+            .and_then(|mode| serde_json::from_str(&mode).ok())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_sort_mode(
+        &self,
+        folder: &Path,
+        mode: SortMode,
+    ) -> Result<(), CurrentDirError> {
+        let folder = folder.to_string_lossy().to_string();
+        let mode = serde_json::to_string(&mode).map_err(|_| CurrentDirError::CannotSerialize)?;
+        self.connection
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO sort_modes (path, mode) VALUES (?1, ?2)
+                     ON CONFLICT (path) DO UPDATE SET mode = ?2",
+                    (folder, mode),
+                )
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collator, natural_cmp, parse_locale, SortKey, SortMode};
+    use crate::{database::AppDatabase, FileData, FileType};
+    use icu_locid::Locale;
+    use std::{cmp::Ordering, path::Path};
+
+    fn file(name: &str, filetype: FileType, size: u64) -> FileData {
+        let mut file = FileData::new(name, &Path::new("/").join(name), filetype);
+        file.size = Some(size);
+        file
+    }
+
+    fn names(files: &[FileData]) -> Vec<&str> {
+        files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    #[test]
+    fn numbers_are_compared_naturally() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("File10", "file9"), Ordering::Greater);
+        assert_eq!(natural_cmp("a", "B"), Ordering::Less);
+        assert_eq!(natural_cmp("x01", "x1"), Ordering::Less);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+        assert_eq!(natural_cmp("abc", "abcd"), Ordering::Less);
+    }
+
+    #[test]
+    fn names_are_collated_by_locale() {
+        let neutral = collator(&Locale::UND).unwrap();
+        let finnish = collator(&parse_locale("fi_FI.UTF-8").unwrap()).unwrap();
+
+        assert_eq!(neutral.compare("öljy", "zeta"), Ordering::Less);
+        assert_eq!(finnish.compare("öljy", "zeta"), Ordering::Greater);
+        assert_eq!(neutral.compare("été", "etz"), Ordering::Less);
+        // Composed and decomposed accents are the same letter
+        assert_eq!(neutral.compare("\u{e9}", "e\u{301}"), Ordering::Equal);
+        assert_ne!(natural_cmp("\u{e9}", "e\u{301}"), Ordering::Equal);
+        assert_eq!(parse_locale("C"), None);
+    }
+
+    #[test]
+    fn size_sort_keeps_folders_first() {
+        let mut files = vec![
+            file("small.txt", FileType::File, 1),
+            file("folder", FileType::Folder, 4096),
+            file("big.txt", FileType::File, 100),
+        ];
+        let mode = SortMode {
+            key: SortKey::Size,
+            descending: true,
+            folders_first: true,
+        };
+
+        mode.sort(&mut files);
+        assert_eq!(names(&files), vec!["folder", "big.txt", "small.txt"]);
+
+        SortMode {
+            folders_first: false,
+            ..mode
+        }
+        .sort(&mut files);
+        assert_eq!(names(&files), vec!["folder", "big.txt", "small.txt"]);
+
+        SortMode {
+            descending: false,
+            folders_first: false,
+            ..mode
+        }
+        .sort(&mut files);
+        assert_eq!(names(&files), vec!["small.txt", "big.txt", "folder"]);
+    }
+
+    #[test]
+    fn extension_sort_groups_extensions() {
+        let mut files = vec![
+            file("b.txt", FileType::File, 0),
+            file("a.rs", FileType::File, 0),
+            file("c.rs", FileType::File, 0),
+            file("Makefile", FileType::File, 0),
+        ];
+        SortMode {
+            key: SortKey::Extension,
+            descending: false,
+            folders_first: true,
+        }
+        .sort(&mut files);
+
+        assert_eq!(names(&files), vec!["Makefile", "a.rs", "c.rs", "b.txt"]);
+    }
+
+    #[test]
+    fn type_sort_ignores_sniffed_types() {
+        let mut files = vec![
+            file("photo.png", FileType::File, 0),
+            file("notes.txt", FileType::File, 0),
+            file("folder", FileType::Folder, 0),
+        ];
+        // A sniffed type that disagrees with the extension doesn't matter
+        files[1].mime = Some("image/jpeg".to_string());
+        SortMode::default().sort(&mut files);
+
+        assert_eq!(names(&files), vec!["folder", "photo.png", "notes.txt"]);
+    }
+
+    #[tokio::test]
+    async fn sort_mode_is_remembered_per_folder() {
+        let database = AppDatabase::open_in_memory().await.unwrap();
+        let mode = SortMode {
+            key: SortKey::Modified,
+            descending: true,
+            folders_first: false,
+        };
+
+        database.set_sort_mode(Path::new("/a"), mode).await.unwrap();
+
+        assert_eq!(database.get_sort_mode(Path::new("/a")).await.unwrap(), mode);
+        assert_eq!(
+            database.get_sort_mode(Path::new("/b")).await.unwrap(),
+            SortMode::default()
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use super::{filecache::FileCache, CurrentDir, CurrentDirError};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+
+pub type TabId = u32;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TabInfo {
+    pub id: TabId,
+    pub path: PathBuf,
+}
+
+/// Every tab has its own `CurrentDir`, but all of them search from the same
+/// file cache
+#[derive(Debug)]
+pub struct TabRegistry {
+    tabs: HashMap<TabId, Arc<Mutex<CurrentDir>>>,
+    file_cache: Arc<FileCache>,
+    next_id: TabId,
+}
+
+impl TabRegistry {
+    pub fn new(file_cache: Arc<FileCache>) -> Self {
+        TabRegistry {
+            tabs: HashMap::new(),
+            file_cache,
+            next_id: 0,
+        }
+    }
+
+    pub fn open_tab(&mut self, path: &Path) -> Result<TabId, CurrentDirError> {
+        let current_dir = CurrentDir::new(path, Arc::clone(&self.file_cache))?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tabs.insert(id, Arc::new(Mutex::new(current_dir)));
+        Ok(id)
+    }
+
+    pub fn close_tab(&mut self, id: TabId) -> Result<(), CurrentDirError> {
+        self.tabs
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(CurrentDirError::TabNotFound { id })
+    }
+
+    /// The tab is returned by itself so that the registry doesn't have to stay
+    /// locked while the tab is used
+    pub fn get(&self, id: TabId) -> Result<Arc<Mutex<CurrentDir>>, CurrentDirError> {
+        self.tabs
+            .get(&id)
+            .map(Arc::clone)
+            .ok_or(CurrentDirError::TabNotFound { id })
+    }
+
+    pub async fn list_tabs(&self) -> Vec<TabInfo> {
+        let mut tabs = vec![];
+        for (id, tab) in self.tabs.iter() {
+            tabs.push(TabInfo {
+                id: *id,
+                path: tab.lock().await.get_path().to_owned(),
+            });
+        }
+        tabs.sort_unstable_by_key(|tab| tab.id);
+        tabs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TabRegistry;
+    use crate::filecache::FileCache;
+    use std::{path::Path, sync::Arc};
+
+    #[tokio::test]
+    async fn tabs_are_independent() {
+        let mut registry = TabRegistry::new(Arc::new(FileCache::create_secondary().await));
+        let first = registry.open_tab(Path::new("/")).unwrap();
+        let second = registry.open_tab(Path::new("/")).unwrap();
+
+        registry
+            .get(second)
+            .unwrap()
+            .lock()
+            .await
+            .move_to_dir(&std::env::temp_dir(), false)
+            .unwrap();
+
+        let tabs = registry.list_tabs().await;
+        assert_ne!(first, second);
+        assert_eq!(tabs[0].path, Path::new("/"));
+        assert_ne!(tabs[1].path, Path::new("/"));
+    }
+
+    #[tokio::test]
+    async fn closed_tab_is_not_found() {
+        let mut registry = TabRegistry::new(Arc::new(FileCache::create_secondary().await));
+        let id = registry.open_tab(Path::new("/")).unwrap();
+
+        registry.close_tab(id).unwrap();
+
+        assert!(registry.get(id).is_err());
+        assert!(registry.close_tab(id).is_err());
+        assert!(registry.open_tab(Path::new("/does/not/exist")).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use super::{platform, uri, CurrentDirError};
+use std::{
+    env, fs,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Semaphore;
+
+/// Size of the "normal" thumbnails of the freedesktop thumbnail spec
+const THUMBNAIL_SIZE: u32 = 128;
+const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+const URI_KEY: &str = "Thumb::URI";
+const MTIME_KEY: &str = "Thumb::MTime";
+pub const PROTOCOL: &str = "thumbnail";
+
+/// Tells apart the temporary files of thumbnails written at the same time
+static TEMPORARY_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Generates thumbnails into `~/.cache/thumbnails/normal`, following
+/// https://specifications.freedesktop.org/thumbnail-spec/latest/
+#[derive(Debug)]
+pub struct ThumbnailService {
+    cache_dir: PathBuf,
+    workers: Arc<Semaphore>,
+}
+
+impl Default for ThumbnailService {
+    fn default() -> Self {
+        let cache_home = env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".cache")
+            });
+        ThumbnailService::new(cache_home.join("thumbnails").join("normal"))
+    }
+}
+
+impl ThumbnailService {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        let workers = std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(4);
+        ThumbnailService {
+            cache_dir,
+            workers: Arc::new(Semaphore::new(workers)),
+        }
+    }
+
+    pub fn is_supported(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| SUPPORTED_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+            .unwrap_or(false)
+    }
+
+    pub fn thumbnail_path(&self, path: &Path) -> PathBuf {
+        let digest = md5::compute(uri::file_uri(path));
+        self.cache_dir.join(format!("{:x}.png", digest))
+    }
+
+    /// Returns the path of the thumbnail if it is up to date, without
+    /// generating anything
+    pub fn get_existing(&self, path: &Path) -> Option<PathBuf> {
+        if !ThumbnailService::is_supported(path) {
+            return None;
+        }
+        let mtime = platform::modified(&fs::metadata(path).ok()?);
+        let thumbnail_path = self.thumbnail_path(path);
+        (read_thumbnail_mtime(&thumbnail_path) == Some(mtime)).then_some(thumbnail_path)
+    }
+
+    /// Returns the path of an up to date thumbnail, generating it if needed
+    pub fn get_or_create(&self, path: &Path) -> Result<PathBuf, CurrentDirError> {
+        let error = |reason: String| CurrentDirError::CannotCreateThumbnail { reason };
+
+        if !ThumbnailService::is_supported(path) {
+            return Err(error(format!("{} isn't a supported image", path.display())));
+        }
+        let mtime = fs::metadata(path)
+            .map(|metadata| platform::modified(&metadata))
+            .map_err(|err| error(err.to_string()))?;
+        let thumbnail_path = self.thumbnail_path(path);
+
+        if read_thumbnail_mtime(&thumbnail_path) == Some(mtime) {
+            return Ok(thumbnail_path);
+        }
+
+        let image = image::open(path)
+            .map_err(|err| error(err.to_string()))?
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .to_rgba8();
+        create_private_dir(&self.cache_dir).map_err(|err| error(err.to_string()))?;
+
+        // Written to a temporary file first so that a half written thumbnail
+        // is never read by anyone
+        let temporary_path = thumbnail_path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = write_thumbnail(&image, &uri::file_uri(path), mtime, &temporary_path)
+            .and_then(|_| fs::rename(&temporary_path, &thumbnail_path).map_err(|e| e.to_string()));
+        if let Err(reason) = written {
+            let _ = fs::remove_file(&temporary_path);
+            return Err(error(reason));
+        }
+        Ok(thumbnail_path)
+    }
+
+    /// Generates the thumbnails on a background pool, calling `on_ready` for
+    /// each image whose thumbnail could be created
+    pub fn generate(
+        self: &Arc<Self>,
+        paths: Vec<PathBuf>,
+        on_ready: impl Fn(&Path) + Send + Sync + 'static,
+    ) {
+        let on_ready = Arc::new(on_ready);
+
+        for path in paths
+            .into_iter()
+            .filter(|path| ThumbnailService::is_supported(path))
+        {
+            let service = Arc::clone(self);
+            let on_ready = Arc::clone(&on_ready);
+
+            tokio::spawn(async move {
+                let _permit = Arc::clone(&service.workers).acquire_owned().await;
+                let created =
+                    tokio::task::spawn_blocking(move || service.get_or_create(&path).map(|_| path))
+                        .await;
+
+                if let Ok(Ok(path)) = created {
+                    on_ready(&path);
+                }
+            });
+        }
+    }
+}
+
+/// The spec asks for thumbnails to be readable only by their owner, since
+/// they show what is in the files
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::create_dir_all(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700))
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(path)
+}
+
+#[cfg(unix)]
+fn create_private_file(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_private_file(path: &Path) -> std::io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+fn write_thumbnail(
+    image: &image::RgbaImage,
+    uri: &str,
+    mtime: i64,
+    destination: &Path,
+) -> Result<(), String> {
+    let file = create_private_file(destination).map_err(|err| err.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .add_text_chunk(URI_KEY.to_owned(), uri.to_owned())
+        .and_then(|_| encoder.add_text_chunk(MTIME_KEY.to_owned(), mtime.to_string()))
+        .and_then(|_| encoder.write_header())
+        .and_then(|mut writer| writer.write_image_data(image.as_raw()))
+        .map_err(|err| err.to_string())
+}
+
+fn read_thumbnail_mtime(thumbnail_path: &Path) -> Option<i64> {
+    let file = fs::File::open(thumbnail_path).ok()?;
+    let reader = png::Decoder::new(file).read_info().ok()?;
+    let mtime = reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == MTIME_KEY)?
+        .text
+        .parse()
+        .ok();
+    mtime
+}
+
+/// Parses the image path from the URL of the thumbnail protocol, eg.
+/// `thumbnail://localhost/home/user/a%20b.png`
+pub fn path_from_protocol_uri(uri: &str) -> Option<PathBuf> {
+    let encoded = uri
+        .strip_prefix(&format!("{}://localhost", PROTOCOL))
+        .or_else(|| uri.strip_prefix(&format!("https://{}.localhost", PROTOCOL)))?;
+    uri::percent_decode(encoded).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path_from_protocol_uri, read_thumbnail_mtime, ThumbnailService};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn protocol_uri_is_parsed() {
+        assert_eq!(
+            path_from_protocol_uri("thumbnail://localhost/home/a%20b/%C3%A4.png"),
+            Some(Path::new("/home/a b/ä.png").to_owned())
+        );
+        assert_eq!(
+            path_from_protocol_uri("https://thumbnail.localhost/tmp/x.png"),
+            Some(Path::new("/tmp/x.png").to_owned())
+        );
+        assert_eq!(path_from_protocol_uri("other://localhost/x.png"), None);
+    }
+
+    #[test]
+    fn thumbnail_is_created_and_reused() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        let image_path = root.join("image.png");
+        image::RgbaImage::new(512, 256).save(&image_path).unwrap();
+
+        let service = ThumbnailService::new(root.join("cache"));
+        let missing = service.get_existing(&image_path);
+        let thumbnail_path = service.get_or_create(&image_path).unwrap();
+        let thumbnail = image::open(&thumbnail_path).unwrap();
+        let modified_before = fs::metadata(&thumbnail_path).unwrap().modified().unwrap();
+        let reused_path = service.get_or_create(&image_path).unwrap();
+        let modified_after = fs::metadata(&reused_path).unwrap().modified().unwrap();
+        let stored_mtime = read_thumbnail_mtime(&thumbnail_path);
+
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 64));
+        assert_eq!(thumbnail_path, reused_path);
+        assert_eq!(missing, None);
+        assert_eq!(service.get_existing(&image_path), Some(thumbnail_path));
+        assert_eq!(modified_before, modified_after);
+        assert!(stored_mtime.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn thumbnails_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        let image_path = root.join("image.png");
+        image::RgbaImage::new(16, 16).save(&image_path).unwrap();
+
+        let service = ThumbnailService::new(root.join("cache"));
+        let thumbnail_path = service.get_or_create(&image_path).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        assert_eq!(mode(&root.join("cache")), 0o700);
+        assert_eq!(mode(&thumbnail_path), 0o600);
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        let service = ThumbnailService::new(std::env::temp_dir());
+
+        assert!(!ThumbnailService::is_supported(Path::new("a.txt")));
+        assert!(ThumbnailService::is_supported(Path::new("a.JPG")));
+        assert!(service
+            .get_or_create(Path::new("/nonexistent.txt"))
+            .is_err());
+    }
+}
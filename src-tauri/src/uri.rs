@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte)
+}
+
+/// Escapes everything except unreserved characters and slashes
+pub fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if is_unreserved(byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Returns `None` for broken escapes or if the result isn't UTF-8
+pub fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+pub fn file_uri(path: &Path) -> String {
+    format!("file://{}", percent_encode(&path.to_string_lossy()))
+}
+
+pub fn path_from_file_uri(uri: &str) -> Option<PathBuf> {
+    uri.strip_prefix("file://")
+        .and_then(percent_decode)
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_uri, path_from_file_uri, percent_decode};
+    use std::path::Path;
+
+    #[test]
+    fn file_uri_is_escaped() {
+        assert_eq!(
+            file_uri(Path::new("/home/user/a b/ä.png")),
+            "file:///home/user/a%20b/%C3%A4.png"
+        );
+    }
+
+    #[test]
+    fn file_uri_is_parsed() {
+        assert_eq!(
+            path_from_file_uri("file:///home/a%20b/%C3%A4"),
+            Some(Path::new("/home/a b/ä").to_owned())
+        );
+        assert_eq!(path_from_file_uri("sftp://host/home"), None);
+    }
+
+    #[test]
+    fn broken_escapes_are_rejected() {
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+}
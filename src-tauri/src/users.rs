@@ -0,0 +1,68 @@
+use std::fs;
+
+const PASSWD_FILE: &str = "/etc/passwd";
+const GROUP_FILE: &str = "/etc/group";
+
+/// Both `/etc/passwd` and `/etc/group` store the name in the first field and
+/// the numeric id in the third one
+fn find_name(database: &str, id: u32) -> Option<String> {
+    database
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.len() > 2 && fields[2].parse::<u32>() == Ok(id))
+        .map(|fields| fields[0].to_owned())
+}
+
+fn find_id(database: &str, name: &str) -> Option<u32> {
+    database
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+}
+
+pub fn user_name(uid: u32) -> Option<String> {
+    find_name(&fs::read_to_string(PASSWD_FILE).ok()?, uid)
+}
+
+pub fn group_name(gid: u32) -> Option<String> {
+    find_name(&fs::read_to_string(GROUP_FILE).ok()?, gid)
+}
+
+pub fn user_id(name: &str) -> Option<u32> {
+    find_id(&fs::read_to_string(PASSWD_FILE).ok()?, name)
+}
+
+pub fn group_id(name: &str) -> Option<u32> {
+    find_id(&fs::read_to_string(GROUP_FILE).ok()?, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_id, find_name};
+
+    const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+vili:x:1000:1000:Vili,,,:/home/vili:/bin/zsh";
+
+    #[test]
+    fn name_is_found_by_id() {
+        assert_eq!(find_name(PASSWD, 0), Some(String::from("root")));
+        assert_eq!(find_name(PASSWD, 1000), Some(String::from("vili")));
+        assert_eq!(find_name(PASSWD, 1234), None);
+    }
+
+    #[test]
+    fn id_is_found_by_name() {
+        assert_eq!(find_id(PASSWD, "daemon"), Some(1));
+        assert_eq!(find_id(PASSWD, "nobody"), None);
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        assert_eq!(
+            find_name("garbage\n\nwheel:x:10:vili", 10),
+            Some(String::from("wheel"))
+        );
+    }
+}
@@ -0,0 +1,622 @@
+use super::{
+    archive::ArchiveKind,
+    mime::{self, MimeCache, SpecialFile},
+    platform,
+    sftp::SftpBackend,
+    watcher::{FolderChange, FolderWatcher},
+    webdav::WebDavBackend,
+    FileData, FileType,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Debug},
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Searching a remote folder stops after this many matches
+pub const MAX_FOUND: usize = 1000;
+
+/// A server address such as `sftp://user@host:2222`. Paths on the server are
+/// written after it, like `sftp://user@host:2222/home/user`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemoteUrl {
+    pub scheme: String,
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl RemoteUrl {
+    const SCHEMES: &'static [&'static str] = &["sftp", "dav", "davs"];
+
+    /// Splits a remote path into the server and the absolute path on it.
+    /// The path is empty when none was given.
+    pub fn parse(path: &Path) -> Option<(RemoteUrl, PathBuf)> {
+        let (scheme, rest) = path.to_str()?.split_once("://")?;
+        if !RemoteUrl::SCHEMES.contains(&scheme) {
+            return None;
+        }
+        let (authority, inner) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+        let (user, address) = match authority.rsplit_once('@') {
+            Some((user, address)) => (Some(user.to_owned()), address),
+            None => (None, authority),
+        };
+        // IPv6 addresses are written in brackets because of their colons
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, Some(port.parse().ok()?)),
+            _ => (address, None),
+        };
+        if host.is_empty() {
+            return None;
+        }
+
+        Some((
+            RemoteUrl {
+                scheme: scheme.to_owned(),
+                user,
+                host: host.to_owned(),
+                port,
+            },
+            Path::new(inner).components().collect(),
+        ))
+    }
+
+    /// The remote path of `inner`, which should be absolute
+    pub fn join(&self, inner: &Path) -> PathBuf {
+        PathBuf::from(format!("{}{}", self, inner.display()))
+    }
+
+    /// The path on the server, if `path` is on this server
+    pub fn inner(&self, path: &Path) -> io::Result<PathBuf> {
+        match RemoteUrl::parse(path) {
+            Some((url, inner)) if url == *self => Ok(inner),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} isn't on {}", path.display(), self),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RemoteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://", self.scheme)?;
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        write!(f, "{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the entries of a path really live. `CurrentDir::path` can point
+/// inside an archive, for example `/home/user/photos.zip/2023`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(PathBuf),
+    Archive {
+        /// The archive file on the local disk
+        archive: PathBuf,
+        /// Relative path inside the archive, empty for its root
+        inner: PathBuf,
+    },
+    Remote {
+        url: RemoteUrl,
+        /// Absolute path on the server
+        path: PathBuf,
+    },
+}
+
+impl Location {
+    /// Remote paths start with their url. Otherwise the innermost archive
+    /// file that `backend` has on the path decides the location.
+    pub fn resolve(backend: &dyn VfsBackend, path: &Path) -> Location {
+        if let Some((url, path)) = RemoteUrl::parse(path) {
+            return Location::Remote { url, path };
+        }
+        // Rebuilding the path drops trailing slashes, which would make the
+        // archive file look like a missing folder
+        let path = path.components().collect::<PathBuf>();
+        for ancestor in path.ancestors() {
+            let is_file = || {
+                backend
+                    .stat(ancestor)
+                    .is_ok_and(|metadata| metadata.filetype == FileType::File)
+            };
+            if ArchiveKind::from_path(ancestor).is_some() && is_file() {
+                if let Ok(inner) = path.strip_prefix(ancestor) {
+                    return Location::Archive {
+                        archive: ancestor.to_owned(),
+                        inner: inner.to_owned(),
+                    };
+                }
+            }
+        }
+        Location::Local(path)
+    }
+}
+
+/// The parent folder of a path, which stays on the same server for remote
+/// paths. `None` at the root.
+pub fn parent(path: &Path) -> Option<PathBuf> {
+    match RemoteUrl::parse(path) {
+        Some((url, inner)) => inner.parent().map(|parent| url.join(parent)),
+        None => path.parent().map(Path::to_owned),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VfsMetadata {
+    pub filetype: FileType,
+    pub size: u64,
+    /// Seconds since the unix epoch
+    pub modified: i64,
+    /// Zero on backends that have no inodes
+    pub inode: u64,
+    /// Set for FIFOs, sockets and devices, whose `filetype` is `File`
+    pub special: Option<SpecialFile>,
+}
+
+impl From<&fs::Metadata> for VfsMetadata {
+    fn from(value: &fs::Metadata) -> Self {
+        VfsMetadata {
+            filetype: FileType::from(value.file_type()),
+            size: value.len(),
+            modified: platform::modified(value),
+            inode: platform::inode(value).unwrap_or(0),
+            special: SpecialFile::of(&value.file_type()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VfsEntry {
+    pub path: PathBuf,
+    pub metadata: VfsMetadata,
+}
+
+/// Called with the changes to the entries of a watched folder
+pub type OnChanges = Box<dyn Fn(Vec<FolderChange>) + Send + Sync + 'static>;
+
+/// Keeps reporting changes until it is dropped
+pub trait VfsWatcher: Send + Debug {
+    /// Moves the watch to another folder
+    fn follow(&mut self, folder: &Path) -> io::Result<()>;
+    /// Whether anything has changed since the last call
+    fn take_changed(&self) -> bool;
+}
+
+/// The file operations that `CurrentDir` needs. Paths are absolute paths of
+/// the backend, links are never followed unless stated otherwise.
+pub trait VfsBackend: Send + Sync + Debug {
+    /// Makes the path absolute and resolves `..` and links
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+    fn list(&self, folder: &Path) -> io::Result<Vec<VfsEntry>>;
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata>;
+    /// Opens a file for reading, following links
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+    /// Creates an empty file or folder. Fails if the path is already taken.
+    fn create(&self, path: &Path, filetype: FileType) -> io::Result<()>;
+    /// Removes a file, a link or an empty folder
+    fn remove(&self, path: &Path) -> io::Result<()>;
+    /// Moves an entry to a new path. Fails if the target is already taken,
+    /// an existing entry is never replaced.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Creates the file or replaces its contents. Returns the bytes written.
+    fn store(&self, path: &Path, contents: &mut dyn Read) -> io::Result<u64>;
+    /// Copies a file or a folder without passing the contents through this
+    /// machine. Backends that can't do it return `Unsupported`.
+    fn copy_inside(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+    fn watch(&self, folder: &Path, on_changes: OnChanges) -> io::Result<Box<dyn VfsWatcher>>;
+
+    /// Backends that can't sniff the content cheaply go by the extension
+    fn mime(&self, path: &Path, metadata: &VfsMetadata) -> &'static str {
+        match (metadata.filetype, metadata.special) {
+            (FileType::Folder, _) => mime::DIRECTORY,
+            (FileType::Link, _) => mime::SYMLINK,
+            (FileType::File, Some(special)) => special.mime(),
+            (FileType::File, None) => mime::from_extension(path).unwrap_or(mime::UNKNOWN),
+        }
+    }
+
+    /// Names that aren't valid UTF-8 can't be shown, so they have no data
+    fn file_data(&self, path: &Path, metadata: &VfsMetadata) -> Option<FileData> {
+        Some(FileData {
+            name: path.file_name()?.to_str()?.to_owned(),
+            path: path.to_owned(),
+            filetype: metadata.filetype,
+            mime: Some(self.mime(path, metadata).to_owned()),
+            size: Some(metadata.size),
+            modified: Some(metadata.modified),
+        })
+    }
+}
+
+/// The disk of this machine
+#[derive(Debug, Default)]
+pub struct LocalBackend {
+    mime_cache: MimeCache,
+}
+
+impl VfsBackend for LocalBackend {
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    /// Entries that disappear while the folder is read are left out
+    fn list(&self, folder: &Path) -> io::Result<Vec<VfsEntry>> {
+        Ok(fs::read_dir(folder)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some(VfsEntry {
+                    path: entry.path(),
+                    metadata: VfsMetadata::from(&metadata),
+                })
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        fs::symlink_metadata(path).map(|metadata| VfsMetadata::from(&metadata))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn create(&self, path: &Path, filetype: FileType) -> io::Result<()> {
+        match filetype {
+            FileType::Folder => fs::create_dir(path),
+            FileType::File => OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .map(|_| ()),
+            FileType::Link => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "links can't be created without a target",
+            )),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        match fs::symlink_metadata(path)?.is_dir() {
+            true => fs::remove_dir(path),
+            false => fs::remove_file(path),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if to.symlink_metadata().is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ));
+        }
+        fs::rename(from, to)
+    }
+
+    fn store(&self, path: &Path, contents: &mut dyn Read) -> io::Result<u64> {
+        io::copy(contents, &mut File::create(path)?)
+    }
+
+    fn watch(&self, folder: &Path, on_changes: OnChanges) -> io::Result<Box<dyn VfsWatcher>> {
+        FolderWatcher::new(folder, on_changes)
+            .map(|watcher| Box::new(watcher) as Box<dyn VfsWatcher>)
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    fn mime(&self, path: &Path, metadata: &VfsMetadata) -> &'static str {
+        self.mime_cache.lookup(
+            path,
+            metadata.filetype,
+            metadata.special,
+            (metadata.inode, metadata.modified),
+        )
+    }
+}
+
+impl VfsWatcher for FolderWatcher {
+    fn follow(&mut self, folder: &Path) -> io::Result<()> {
+        FolderWatcher::follow(self, folder).map_err(|err| io::Error::other(err.to_string()))
+    }
+
+    fn take_changed(&self) -> bool {
+        FolderWatcher::take_changed(self)
+    }
+}
+
+/// Remote backends by server, so that all the folders of a server share one
+/// connection
+#[derive(Debug, Default)]
+pub struct Connections {
+    remotes: Mutex<HashMap<RemoteUrl, Arc<dyn VfsBackend>>>,
+}
+
+impl Connections {
+    /// Connects on first use
+    pub fn get(&self, url: &RemoteUrl) -> io::Result<Arc<dyn VfsBackend>> {
+        let mut remotes = self.remotes.lock().unwrap();
+        if let Some(backend) = remotes.get(url) {
+            return Ok(Arc::clone(backend));
+        }
+        let backend: Arc<dyn VfsBackend> = match url.scheme.as_str() {
+            "sftp" => Arc::new(SftpBackend::connect(url)?),
+            "dav" | "davs" => Arc::new(WebDavBackend::connect(url)?),
+            scheme => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{} isn't supported", scheme),
+                ))
+            }
+        };
+        remotes.insert(url.clone(), Arc::clone(&backend));
+        Ok(backend)
+    }
+
+    /// Makes the next `get` connect again, for example after the server
+    /// has closed the connection
+    pub fn forget(&self, url: &RemoteUrl) {
+        self.remotes.lock().unwrap().remove(url);
+    }
+}
+
+/// Walks the folders under `root` breadth first, without following links.
+/// Matching works like the file cache: names start with `name`, ignoring
+/// case, or are equal to it when `exact` is set. Unreadable folders are
+/// skipped.
+pub fn find(backend: &dyn VfsBackend, root: &Path, name: &str, exact: bool) -> Vec<FileData> {
+    let lowercase = name.to_lowercase();
+    let mut found = vec![];
+    let mut folders = VecDeque::from([root.to_owned()]);
+    while let Some(folder) = folders.pop_front() {
+        for entry in backend.list(&folder).unwrap_or_default() {
+            if entry.metadata.filetype == FileType::Folder {
+                folders.push_back(entry.path.clone());
+            }
+            let Some(file_name) = entry.path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let matches = match exact {
+                true => file_name == name,
+                false => file_name.to_lowercase().starts_with(&lowercase),
+            };
+            if matches {
+                found.extend(backend.file_data(&entry.path, &entry.metadata));
+                if found.len() >= MAX_FOUND {
+                    return found;
+                }
+            }
+        }
+    }
+    found
+}
+
+/// The copies that were made and the entries that were left out
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Copied {
+    pub paths: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Copies a file or a folder with everything in it, possibly between two
+/// backends. Links are copied as the files they point to. Links to folders,
+/// FIFOs, sockets, devices and links that can't be followed are returned as
+/// skipped.
+pub fn copy(
+    from_backend: &dyn VfsBackend,
+    from: &Path,
+    to_backend: &dyn VfsBackend,
+    to: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    let mut skipped = vec![];
+    let metadata = from_backend.stat(from)?;
+    if metadata.special.is_some() {
+        return Ok(vec![from.to_owned()]);
+    }
+    match metadata.filetype {
+        FileType::Folder => {
+            to_backend.create(to, FileType::Folder)?;
+            for entry in from_backend.list(from)? {
+                let Some(name) = entry.path.file_name() else {
+                    continue;
+                };
+                skipped.extend(copy(from_backend, &entry.path, to_backend, &to.join(name))?);
+            }
+        }
+        FileType::File => {
+            to_backend.store(to, &mut from_backend.open(from)?)?;
+        }
+        FileType::Link => {
+            let target = from_backend
+                .canonicalize(from)
+                .and_then(|target| from_backend.stat(&target));
+            let contents = match target {
+                Ok(target) if target.filetype == FileType::File && target.special.is_none() => {
+                    from_backend.open(from)
+                }
+                _ => Err(io::Error::from(io::ErrorKind::Unsupported)),
+            };
+            match contents {
+                Ok(mut contents) => {
+                    to_backend.store(to, &mut contents)?;
+                }
+                Err(_) => skipped.push(from.to_owned()),
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy, find, parent, LocalBackend, Location, RemoteUrl, VfsBackend};
+    use crate::{memfs::MemoryBackend, FileType};
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    #[test]
+    fn paths_inside_archives_are_resolved() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("folder.zip")).unwrap();
+        fs::write(root.join("photos.zip"), "").unwrap();
+
+        let backend = LocalBackend::default();
+        let inside = Location::resolve(
+            &backend,
+            &root.join("photos.zip").join("2023").join("a.jpg"),
+        );
+        let archive_root = Location::resolve(&backend, &root.join("photos.zip"));
+        let folder = Location::resolve(&backend, &root.join("folder.zip"));
+
+        assert_eq!(
+            inside,
+            Location::Archive {
+                archive: root.join("photos.zip"),
+                inner: PathBuf::from("2023/a.jpg"),
+            }
+        );
+        assert_eq!(
+            archive_root,
+            Location::Archive {
+                archive: root.join("photos.zip"),
+                inner: PathBuf::new(),
+            }
+        );
+        assert_eq!(folder, Location::Local(root.join("folder.zip")));
+    }
+
+    #[test]
+    fn remote_urls_are_parsed() {
+        let (url, inner) = RemoteUrl::parse(Path::new("sftp://me@build:2222/srv/logs/")).unwrap();
+        let (ipv6, _) = RemoteUrl::parse(Path::new("sftp://[::1]/")).unwrap();
+
+        assert_eq!(url.user.as_deref(), Some("me"));
+        assert_eq!((url.host.as_str(), url.port), ("build", Some(2222)));
+        assert_eq!(inner, PathBuf::from("/srv/logs"));
+        assert_eq!(
+            parent(&url.join(&inner)),
+            Some(PathBuf::from("sftp://me@build:2222/srv"))
+        );
+        assert_eq!(parent(Path::new("sftp://me@build:2222/")), None);
+        assert_eq!((ipv6.host.as_str(), ipv6.port), ("[::1]", None));
+        assert_eq!(RemoteUrl::parse(Path::new("ftp://host/")), None);
+        assert_eq!(RemoteUrl::parse(Path::new("/home/sftp://host")), None);
+        assert!(matches!(
+            Location::resolve(&LocalBackend::default(), Path::new("sftp://host")),
+            Location::Remote { path, .. } if path == PathBuf::new()
+        ));
+    }
+
+    #[test]
+    fn folders_are_copied_between_backends() {
+        let source = MemoryBackend::new();
+        source
+            .create(Path::new("/project"), FileType::Folder)
+            .unwrap();
+        source
+            .create(Path::new("/project/src"), FileType::Folder)
+            .unwrap();
+        source
+            .write(Path::new("/project/src/main.rs"), b"fn main() {}")
+            .unwrap();
+        source
+            .symlink(Path::new("src/main.rs"), Path::new("/project/main"))
+            .unwrap();
+        source
+            .symlink(Path::new("src"), Path::new("/project/code"))
+            .unwrap();
+        let destination = MemoryBackend::new();
+
+        let skipped = copy(
+            &source,
+            Path::new("/project"),
+            &destination,
+            Path::new("/copy"),
+        )
+        .unwrap();
+        let found = find(&destination, Path::new("/"), "MAIN", false);
+
+        assert_eq!(skipped, vec![PathBuf::from("/project/code")]);
+        assert_eq!(destination.stat(Path::new("/copy/main")).unwrap().size, 12);
+        assert_eq!(
+            found
+                .iter()
+                .map(|file| file.path.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("/copy/main"),
+                PathBuf::from("/copy/src/main.rs")
+            ]
+        );
+        assert!(find(&destination, Path::new("/"), "main", true).len() == 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_to_folders_and_fifos_are_skipped_on_disk() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::create_dir_all(root.join("project/src")).unwrap();
+        fs::write(root.join("project/src/main.rs"), "fn main() {}").unwrap();
+        std::os::unix::fs::symlink("src", root.join("project/code")).unwrap();
+        nix::unistd::mkfifo(&root.join("project/pipe"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let backend = LocalBackend::default();
+
+        let mut skipped = copy(
+            &backend,
+            &root.join("project"),
+            &backend,
+            &root.join("copy"),
+        )
+        .unwrap();
+        skipped.sort();
+
+        assert_eq!(
+            skipped,
+            vec![root.join("project/code"), root.join("project/pipe")]
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("copy/src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert!(!root.join("copy/pipe").exists());
+    }
+
+    #[test]
+    fn renaming_never_replaces_the_target() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        let disk = LocalBackend::default();
+        let memory = MemoryBackend::new();
+        memory.write(Path::new("/a.txt"), b"a").unwrap();
+        memory.write(Path::new("/b.txt"), b"b").unwrap();
+
+        assert!(disk
+            .rename(&root.join("a.txt"), &root.join("b.txt"))
+            .is_err());
+        assert!(memory
+            .rename(Path::new("/a.txt"), Path::new("/b.txt"))
+            .is_err());
+        assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "b");
+        assert_eq!(memory.stat(Path::new("/a.txt")).unwrap().size, 1);
+    }
+}
@@ -0,0 +1,286 @@
+use super::CurrentDirError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::time::{timeout_at, Instant};
+
+const MOUNTINFO_FILE: &str = "/proc/self/mountinfo";
+const LABELS_FOLDER: &str = "/dev/disk/by-label";
+const UUIDS_FOLDER: &str = "/dev/disk/by-uuid";
+/// Links named by the major and minor number of every block device
+const SYS_BLOCK_FOLDER: &str = "/sys/dev/block";
+
+/// Shares that don't have a device but are still worth showing
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "davfs",
+];
+/// How long shares that stopped answering are waited on for their space
+const SPACE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Read-only package images that would crowd out the real volumes
+const HIDDEN_FILESYSTEMS: &[&str] = &["squashfs"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Volume {
+    pub device: String,
+    pub mount_point: PathBuf,
+    pub filesystem: String,
+    pub label: Option<String>,
+    pub uuid: Option<String>,
+    /// Space in bytes, `None` when the volume didn't answer
+    pub total: Option<u64>,
+    /// What an unprivileged user can still use
+    pub free: Option<u64>,
+    pub used: Option<u64>,
+    pub removable: bool,
+    pub read_only: bool,
+}
+
+/// One line of mountinfo, only with the fields that are used
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mount {
+    device_number: String,
+    mount_point: PathBuf,
+    filesystem: String,
+    source: String,
+    read_only: bool,
+}
+
+/// Spaces and other special characters are written as octal escapes like
+/// `\040`
+fn unescape_octal(value: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..3)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match (byte, escaped) {
+            (b'\\', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The fields are described in proc(5). The optional fields before the `-`
+/// separator vary in number, so the rest is found from the separator.
+fn parse_mountinfo(mountinfo: &str) -> Vec<Mount> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split(' ').collect::<Vec<&str>>();
+            let separator = fields.iter().position(|field| *field == "-")?;
+            let mount_options = fields.get(5)?;
+            let super_options = fields.get(separator + 3).unwrap_or(&"");
+            let is_read_only = |options: &str| options.split(',').any(|option| option == "ro");
+            Some(Mount {
+                device_number: fields.get(2)?.to_string(),
+                mount_point: PathBuf::from(unescape_octal(fields.get(4)?)),
+                filesystem: fields.get(separator + 1)?.to_string(),
+                source: unescape_octal(fields.get(separator + 2)?),
+                read_only: is_read_only(mount_options) || is_read_only(super_options),
+            })
+        })
+        .collect()
+}
+
+/// FUSE mounts such as sshfs can hang like network shares
+fn is_remote(mount: &Mount) -> bool {
+    NETWORK_FILESYSTEMS.contains(&mount.filesystem.as_str())
+        || mount.filesystem.starts_with("fuse.")
+}
+
+fn is_shown(mount: &Mount) -> bool {
+    if HIDDEN_FILESYSTEMS.contains(&mount.filesystem.as_str()) {
+        return false;
+    }
+    mount.source.starts_with("/dev/") || is_remote(mount)
+}
+
+/// udev escapes the names of the links like `\x20`
+fn unescape_hex(value: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .strip_prefix(b"x")
+            .and_then(|tail| tail.get(..2))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        match (byte, escaped) {
+            (b'\\', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Maps the devices the links point to to the names of the links
+fn device_names(folder: &str) -> HashMap<PathBuf, String> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let device = fs::canonicalize(entry.path()).ok()?;
+            Some((device, unescape_hex(entry.file_name().to_str()?)))
+        })
+        .collect()
+}
+
+/// USB sticks often don't set the removable flag, so anything on USB counts
+/// as removable too. Partitions have the flag on their parent disk.
+fn is_removable(device_number: &str) -> bool {
+    let Ok(device) = fs::canonicalize(Path::new(SYS_BLOCK_FOLDER).join(device_number)) else {
+        return false;
+    };
+    let flag_is_set = |folder: &Path| {
+        fs::read_to_string(folder.join("removable")).is_ok_and(|flag| flag.trim() == "1")
+    };
+    device.to_string_lossy().contains("/usb")
+        || flag_is_set(&device)
+        || device.parent().is_some_and(flag_is_set)
+}
+
+/// Returns the total, free and used bytes
+#[cfg(unix)]
+fn space(mount_point: &Path) -> Option<(u64, u64, u64)> {
+    let stat = nix::sys::statvfs::statvfs(mount_point).ok()?;
+    let fragment = stat.fragment_size() as u64;
+    let total = stat.blocks() as u64 * fragment;
+    let free = stat.blocks_available() as u64 * fragment;
+    let used = (stat.blocks() as u64).saturating_sub(stat.blocks_free() as u64) * fragment;
+    Some((total, free, used))
+}
+
+#[cfg(not(unix))]
+fn space(_mount_point: &Path) -> Option<(u64, u64, u64)> {
+    None
+}
+
+/// Volumes backed by a device or a network share. Kernel filesystems such
+/// as proc and cgroup are left out. The space of remote volumes is asked on
+/// blocking tasks, and volumes that don't answer in time are listed without
+/// it.
+pub async fn list_volumes() -> Result<Vec<Volume>, CurrentDirError> {
+    let mountinfo =
+        fs::read_to_string(MOUNTINFO_FILE).map_err(|err| CurrentDirError::CannotReadFile {
+            reason: err.to_string(),
+        })?;
+    let labels = device_names(LABELS_FOLDER);
+    let uuids = device_names(UUIDS_FOLDER);
+
+    let mounts = parse_mountinfo(&mountinfo)
+        .into_iter()
+        .filter(is_shown)
+        .collect::<Vec<Mount>>();
+    let tasks = mounts
+        .iter()
+        .filter(|mount| is_remote(mount))
+        .map(|mount| {
+            let mount_point = mount.mount_point.clone();
+            tokio::task::spawn_blocking(move || space(&mount_point))
+        })
+        .collect::<Vec<_>>();
+    // The shares are asked all at once, so they share one deadline
+    let deadline = Instant::now() + SPACE_TIMEOUT;
+    let mut remote_spaces = vec![];
+    for task in tasks {
+        remote_spaces.push(
+            timeout_at(deadline, task)
+                .await
+                .ok()
+                .and_then(|space| space.ok())
+                .flatten(),
+        );
+    }
+    let mut remote_spaces = remote_spaces.into_iter();
+
+    Ok(mounts
+        .into_iter()
+        .map(|mount| {
+            // Sources such as /dev/mapper/root are links to the real device
+            let device = fs::canonicalize(&mount.source).unwrap_or(PathBuf::from(&mount.source));
+            let space = if is_remote(&mount) {
+                remote_spaces.next().flatten()
+            } else {
+                space(&mount.mount_point)
+            };
+            Volume {
+                label: labels.get(&device).cloned(),
+                uuid: uuids.get(&device).cloned(),
+                total: space.map(|(total, _, _)| total),
+                free: space.map(|(_, free, _)| free),
+                used: space.map(|(_, _, used)| used),
+                removable: is_removable(&mount.device_number),
+                read_only: mount.read_only,
+                device: mount.source,
+                mount_point: mount.mount_point,
+                filesystem: mount.filesystem,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_shown, parse_mountinfo, unescape_hex, Mount};
+    use std::path::PathBuf;
+
+    #[test]
+    fn mountinfo_is_parsed() {
+        let mountinfo = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+23 22 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw
+45 22 8:17 / /run/media/me/My\\040Stick ro,nosuid master:3 shared:30 - vfat /dev/sdb1 rw,fmask=0022
+46 22 0:50 / /mnt/share rw,relatime - nfs4 server:/export rw,vers=4.2";
+        let mounts = parse_mountinfo(mountinfo);
+
+        assert_eq!(
+            mounts[2],
+            Mount {
+                device_number: "8:17".to_string(),
+                mount_point: PathBuf::from("/run/media/me/My Stick"),
+                filesystem: "vfat".to_string(),
+                source: "/dev/sdb1".to_string(),
+                read_only: true,
+            }
+        );
+        assert!(!mounts[0].read_only);
+        assert_eq!(
+            mounts
+                .iter()
+                .filter(|mount| is_shown(mount))
+                .map(|mount| mount.mount_point.to_str().unwrap())
+                .collect::<Vec<&str>>(),
+            vec!["/", "/run/media/me/My Stick", "/mnt/share"]
+        );
+        assert_eq!(unescape_hex("My\\x20Stick"), "My Stick");
+    }
+}
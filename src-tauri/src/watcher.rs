@@ -0,0 +1,301 @@
+use super::{mime::MimeCache, platform, tabs::TabId, CurrentDirError, FileData, FileType};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Changes are collected for this long before they are reported
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum FolderChange {
+    Created { file: FileData },
+    Removed { path: PathBuf },
+    Renamed { from: PathBuf, file: FileData },
+    Modified { file: FileData },
+}
+
+/// Payload of the events sent to the frontend
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FolderChanges {
+    pub tab_id: TabId,
+    pub changes: Vec<FolderChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pending {
+    Created,
+    Removed,
+    Modified,
+    Renamed { from: PathBuf },
+}
+
+/// Merges the raw events of one debounce period so that every path is
+/// reported once
+#[derive(Debug, Default)]
+struct Debouncer {
+    pending: Vec<(PathBuf, Pending)>,
+}
+
+impl Debouncer {
+    fn push(&mut self, path: PathBuf, change: Pending) {
+        // Renames are also reported as a removal and a creation
+        if let Pending::Renamed { from } = &change {
+            self.pending.retain(|(pending_path, pending)| {
+                !(pending_path == from && *pending == Pending::Removed
+                    || *pending_path == path && *pending == Pending::Created)
+            });
+        }
+
+        let Some(index) = self
+            .pending
+            .iter()
+            .position(|(pending, _)| *pending == path)
+        else {
+            self.pending.push((path, change));
+            return;
+        };
+        let (_, previous) = self.pending.remove(index);
+        let merged = match (previous, change) {
+            (Pending::Created, Pending::Removed) => None,
+            (Pending::Renamed { from }, Pending::Removed) => {
+                self.pending.push((from, Pending::Removed));
+                None
+            }
+            (previous @ (Pending::Created | Pending::Renamed { .. }), Pending::Modified) => {
+                Some(previous)
+            }
+            (Pending::Removed, Pending::Created) => Some(Pending::Modified),
+            (_, change) => Some(change),
+        };
+        if let Some(merged) = merged {
+            self.pending.push((path, merged));
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Paths outside `folder` belong to the folder that was watched before
+    fn take(&mut self, folder: &Path, mime_cache: &MimeCache) -> Vec<FolderChange> {
+        self.pending
+            .drain(..)
+            .filter(|(path, _)| path.parent() == Some(folder))
+            .map(|(path, pending)| {
+                let file = file_data(&path, mime_cache);
+                match (pending, file) {
+                    (Pending::Removed, _) => FolderChange::Removed { path },
+                    (Pending::Created, Some(file)) => FolderChange::Created { file },
+                    (Pending::Modified, Some(file)) => FolderChange::Modified { file },
+                    (Pending::Renamed { from }, Some(file)) => FolderChange::Renamed { from, file },
+                    (Pending::Renamed { from }, None) => FolderChange::Removed { path: from },
+                    (_, None) => FolderChange::Removed { path },
+                }
+            })
+            .collect()
+    }
+}
+
+fn classify(event: notify::Event) -> Vec<(PathBuf, Pending)> {
+    let pending = match event.kind {
+        EventKind::Create(_) => Pending::Created,
+        EventKind::Remove(_) => Pending::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            return match <[PathBuf; 2]>::try_from(event.paths) {
+                Ok([from, to]) => vec![(to, Pending::Renamed { from })],
+                Err(_) => vec![],
+            };
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Pending::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Pending::Created,
+        EventKind::Modify(_) => Pending::Modified,
+        _ => return vec![],
+    };
+    event
+        .paths
+        .into_iter()
+        .map(|path| (path, pending.clone()))
+        .collect()
+}
+
+fn file_data(path: &Path, mime_cache: &MimeCache) -> Option<FileData> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    Some(FileData {
+        name: path.file_name()?.to_str()?.to_owned(),
+        path: path.to_owned(),
+        filetype: FileType::from(metadata.file_type()),
+        mime: Some(mime_cache.get(path, &metadata).to_owned()),
+        size: Some(metadata.len()),
+        modified: Some(platform::modified(&metadata)),
+    })
+}
+
+/// Watches the entries of one folder. Dropping it stops the watching.
+#[derive(Debug)]
+pub struct FolderWatcher {
+    watcher: RecommendedWatcher,
+    folder: Arc<Mutex<PathBuf>>,
+    changed: Arc<AtomicBool>,
+}
+
+impl FolderWatcher {
+    /// `on_changes` is called from a separate thread at most once per
+    /// debounce period
+    pub fn new(
+        folder: &Path,
+        on_changes: impl Fn(Vec<FolderChange>) + Send + 'static,
+    ) -> Result<Self, CurrentDirError> {
+        let (sender, receiver) = mpsc::channel::<notify::Result<notify::Event>>();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })
+        .map_err(|err| CurrentDirError::CannotWatch {
+            reason: err.to_string(),
+        })?;
+        let folder_watcher = FolderWatcher {
+            watcher,
+            folder: Arc::new(Mutex::new(PathBuf::new())),
+            changed: Arc::new(AtomicBool::new(false)),
+        };
+
+        let watched = Arc::clone(&folder_watcher.folder);
+        let changed = Arc::clone(&folder_watcher.changed);
+        // The thread stops once the watcher, and with it the sender, is dropped
+        thread::spawn(move || {
+            let mime_cache = MimeCache::default();
+            let mut debouncer = Debouncer::default();
+            let mut deadline = Instant::now();
+            loop {
+                let event = match debouncer.is_empty() {
+                    true => receiver
+                        .recv()
+                        .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                    false => {
+                        receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                };
+                match event {
+                    Ok(Ok(event)) => {
+                        if debouncer.is_empty() {
+                            deadline = Instant::now() + DEBOUNCE;
+                        }
+                        for (path, pending) in classify(event) {
+                            debouncer.push(path, pending);
+                        }
+                    }
+                    Ok(Err(_)) => {}
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        let folder = watched.lock().unwrap().clone();
+                        let changes = debouncer.take(&folder, &mime_cache);
+                        if !changes.is_empty() {
+                            changed.store(true, Ordering::SeqCst);
+                            on_changes(changes);
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        });
+
+        let mut folder_watcher = folder_watcher;
+        folder_watcher.follow(folder)?;
+        Ok(folder_watcher)
+    }
+
+    /// Moves the watch to another folder
+    pub fn follow(&mut self, folder: &Path) -> Result<(), CurrentDirError> {
+        let mut watched = self.folder.lock().unwrap();
+        if *watched == folder {
+            return Ok(());
+        }
+        let _ = self.watcher.unwatch(&watched);
+        *watched = folder.to_owned();
+        self.watcher
+            .watch(folder, RecursiveMode::NonRecursive)
+            .map_err(|err| CurrentDirError::CannotWatch {
+                reason: err.to_string(),
+            })
+    }
+
+    /// Whether anything has changed since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Debouncer, FolderChange, FolderWatcher, Pending};
+    use crate::mime::MimeCache;
+    use std::{fs, path::PathBuf, sync::mpsc, time::Duration};
+
+    #[test]
+    fn bursts_are_merged_per_path() {
+        let folder = PathBuf::from("/does/not/exist");
+        let mut debouncer = Debouncer::default();
+        debouncer.push(folder.join("temp"), Pending::Created);
+        debouncer.push(folder.join("temp"), Pending::Modified);
+        debouncer.push(folder.join("temp"), Pending::Removed);
+        debouncer.push(folder.join("a"), Pending::Removed);
+        debouncer.push(folder.join("b"), Pending::Created);
+        debouncer.push(
+            folder.join("b"),
+            Pending::Renamed {
+                from: folder.join("a"),
+            },
+        );
+        debouncer.push(PathBuf::from("/elsewhere/c"), Pending::Removed);
+
+        // The files don't exist, so the rename is reported as a removal
+        assert_eq!(
+            debouncer.take(&folder, &MimeCache::default()),
+            vec![FolderChange::Removed {
+                path: folder.join("a")
+            }]
+        );
+        assert!(debouncer.is_empty());
+    }
+
+    #[test]
+    fn created_files_are_reported() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_owned();
+        let other_temp = tempfile::tempdir().unwrap();
+        let other = other_temp.path().to_owned();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = FolderWatcher::new(&root, move |changes| {
+            let _ = sender.send(changes);
+        })
+        .unwrap();
+        fs::write(root.join("new.txt"), "hello").unwrap();
+        let first = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        watcher.follow(&other).unwrap();
+        fs::create_dir(other.join("folder")).unwrap();
+        let second = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert!(watcher.take_changed());
+        assert!(!watcher.take_changed());
+        match &first[0] {
+            FolderChange::Created { file } | FolderChange::Modified { file } => {
+                assert_eq!(file.name, "new.txt");
+                assert_eq!(file.size, Some(5));
+            }
+            change => panic!("unexpected change {:?}", change),
+        }
+        assert!(matches!(&second[..], [FolderChange::Created { file }] if file.name == "folder"));
+    }
+}
@@ -0,0 +1,450 @@
+use super::{
+    credentials,
+    uri::{percent_decode, percent_encode},
+    vfs::{OnChanges, RemoteUrl, VfsBackend, VfsEntry, VfsMetadata, VfsWatcher},
+    FileType,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use roxmltree::Node;
+use std::{
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Requests that get no answer in this time fail instead of hanging the tab
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Listings are read whole before parsing. Folders with tens of thousands of
+/// entries stay well below this.
+const MAX_LISTING_SIZE: u64 = 256 * 1024 * 1024;
+
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop>
+</d:propfind>"#;
+
+fn status_error(status: u16, path: &Path) -> io::Error {
+    let kind = match status {
+        401 | 403 => io::ErrorKind::PermissionDenied,
+        // Conflict means that the parent folder is missing
+        404 | 409 => io::ErrorKind::NotFound,
+        405 | 412 => io::ErrorKind::AlreadyExists,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(
+        kind,
+        format!("the server answered {} for {}", status, path.display()),
+    )
+}
+
+fn io_error(err: ureq::Error, path: &Path) -> io::Error {
+    match err {
+        ureq::Error::Status(status, _) => status_error(status, path),
+        ureq::Error::Transport(transport) => io::Error::other(transport.to_string()),
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| is_dav(*child, name))
+}
+
+fn is_dav(node: Node, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().name() == name
+        && node.tag_name().namespace() == Some("DAV:")
+}
+
+/// Hrefs are percent-encoded and can be absolute paths or whole urls
+fn href_path(href: &str) -> Option<PathBuf> {
+    let path = match href.split_once("://") {
+        Some((_, rest)) => &rest[rest.find('/').unwrap_or(rest.len())..],
+        None => href,
+    };
+    let path = Path::new(&percent_decode(path)?)
+        .components()
+        .collect::<PathBuf>();
+    match path.as_os_str().is_empty() {
+        true => Some(PathBuf::from("/")),
+        false => Some(path),
+    }
+}
+
+/// Reads the paths and properties of a 207 Multi-Status answer. Properties
+/// that the server doesn't have are left as zero.
+fn parse_multistatus(xml: &str) -> io::Result<Vec<(PathBuf, VfsMetadata)>> {
+    let document = roxmltree::Document::parse(xml)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    Ok(document
+        .descendants()
+        .filter(|node| is_dav(*node, "response"))
+        .filter_map(|response| {
+            let href = child(response, "href")?.text()?;
+            let prop = response
+                .children()
+                .filter(|node| is_dav(*node, "propstat"))
+                .find(|propstat| {
+                    child(*propstat, "status")
+                        .and_then(|status| status.text())
+                        .is_some_and(|status| status.contains(" 200 "))
+                })
+                .and_then(|propstat| child(propstat, "prop"))?;
+            let text = |name| child(prop, name).and_then(|node| node.text());
+
+            let is_folder =
+                child(prop, "resourcetype").is_some_and(|kind| child(kind, "collection").is_some());
+            let size = text("getcontentlength")
+                .and_then(|size| size.trim().parse().ok())
+                .unwrap_or(0);
+            let modified = text("getlastmodified")
+                .and_then(|date| httpdate::parse_http_date(date.trim()).ok())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs() as i64);
+            Some((
+                href_path(href)?,
+                VfsMetadata {
+                    filetype: match is_folder {
+                        true => FileType::Folder,
+                        false => FileType::File,
+                    },
+                    size,
+                    modified,
+                    inode: 0,
+                    special: None,
+                },
+            ))
+        })
+        .collect())
+}
+
+/// Counts the bytes that an upload has read
+struct Counted<'a> {
+    inner: &'a mut dyn Read,
+    read: u64,
+}
+
+impl Read for Counted<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// A share on a WebDAV server such as Nextcloud. `dav://` urls use http and
+/// `davs://` urls https, for example
+/// `davs://user@cloud.example.com/remote.php/dav/files/user`.
+pub struct WebDavBackend {
+    url: RemoteUrl,
+    /// Scheme, host and port of the http urls
+    origin: String,
+    agent: ureq::Agent,
+    authorization: Option<String>,
+}
+
+impl fmt::Debug for WebDavBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebDavBackend")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebDavBackend {
+    /// Urls with a user log in with the password saved for them, other urls
+    /// are used without logging in. Basic authentication sends the password
+    /// as it is, so logging in is only done over davs://.
+    pub fn connect(url: &RemoteUrl) -> io::Result<Self> {
+        let scheme = match url.scheme.as_str() {
+            "davs" => "https",
+            _ => "http",
+        };
+        if scheme == "http" && url.user.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Logging in to {} needs davs://", url.host),
+            ));
+        }
+        let origin = match url.port {
+            Some(port) => format!("{}://{}:{}", scheme, url.host, port),
+            None => format!("{}://{}", scheme, url.host),
+        };
+        let authorization = url.user.as_ref().map(|user| {
+            let password = credentials::password(url).unwrap_or_default();
+            format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", user, password))
+            )
+        });
+
+        Ok(WebDavBackend {
+            url: url.clone(),
+            origin,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            authorization,
+        })
+    }
+
+    /// The path on the server, `/` when the url has none
+    fn inner(&self, path: &Path) -> io::Result<PathBuf> {
+        let inner = self.url.inner(path)?;
+        match inner.as_os_str().is_empty() {
+            true => Ok(PathBuf::from("/")),
+            false => Ok(inner),
+        }
+    }
+
+    fn address(&self, inner: &Path) -> String {
+        format!(
+            "{}{}",
+            self.origin,
+            percent_encode(&inner.to_string_lossy())
+        )
+    }
+
+    fn request(&self, method: &str, path: &Path) -> io::Result<ureq::Request> {
+        let request = self
+            .agent
+            .request(method, &self.address(&self.inner(path)?));
+        Ok(match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        })
+    }
+
+    fn propfind(&self, path: &Path, depth: u8) -> io::Result<Vec<(PathBuf, VfsMetadata)>> {
+        let response = self
+            .request("PROPFIND", path)?
+            .set("Depth", &depth.to_string())
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND)
+            .map_err(|err| io_error(err, path))?;
+        let mut body = String::new();
+        response
+            .into_reader()
+            .take(MAX_LISTING_SIZE + 1)
+            .read_to_string(&mut body)?;
+        if body.len() as u64 > MAX_LISTING_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the listing of {} is too large", path.display()),
+            ));
+        }
+        parse_multistatus(&body)
+    }
+
+    /// MOVE and COPY never replace an existing target
+    fn transfer(&self, method: &str, from: &Path, to: &Path) -> io::Result<()> {
+        self.request(method, from)?
+            .set("Destination", &self.address(&self.inner(to)?))
+            .set("Overwrite", "F")
+            .call()
+            .map_err(|err| io_error(err, from))?;
+        Ok(())
+    }
+}
+
+impl VfsBackend for WebDavBackend {
+    /// WebDAV has no links, so the path only has to exist
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.stat(path)?;
+        Ok(self.url.join(&self.inner(path)?))
+    }
+
+    fn list(&self, folder: &Path) -> io::Result<Vec<VfsEntry>> {
+        let inner = self.inner(folder)?;
+        Ok(self
+            .propfind(folder, 1)?
+            .into_iter()
+            .filter(|(path, _)| *path != inner)
+            .map(|(path, metadata)| VfsEntry {
+                path: self.url.join(&path),
+                metadata,
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        self.propfind(path, 0)?
+            .into_iter()
+            .next()
+            .map(|(_, metadata)| metadata)
+            .ok_or_else(|| status_error(404, path))
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let response = self
+            .request("GET", path)?
+            .call()
+            .map_err(|err| io_error(err, path))?;
+        Ok(Box::new(response.into_reader()))
+    }
+
+    fn create(&self, path: &Path, filetype: FileType) -> io::Result<()> {
+        let request = match filetype {
+            FileType::Folder => self.request("MKCOL", path)?,
+            FileType::File => self.request("PUT", path)?.set("If-None-Match", "*"),
+            FileType::Link => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "WebDAV has no links",
+                ))
+            }
+        };
+        request.call().map_err(|err| io_error(err, path))?;
+        Ok(())
+    }
+
+    /// Deleting a collection deletes everything in it, so folders are
+    /// checked to be empty first
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if self.stat(path)?.filetype == FileType::Folder && !self.list(path)?.is_empty() {
+            return Err(io::Error::other(format!(
+                "{} isn't an empty folder",
+                path.display()
+            )));
+        }
+        self.request("DELETE", path)?
+            .call()
+            .map_err(|err| io_error(err, path))?;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.transfer("MOVE", from, to)
+    }
+
+    fn copy_inside(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.transfer("COPY", from, to)
+    }
+
+    fn store(&self, path: &Path, contents: &mut dyn Read) -> io::Result<u64> {
+        let mut counted = Counted {
+            inner: contents,
+            read: 0,
+        };
+        self.request("PUT", path)?
+            .send(&mut counted)
+            .map_err(|err| io_error(err, path))?;
+        Ok(counted.read)
+    }
+
+    /// Plain WebDAV has no change notifications
+    fn watch(&self, _folder: &Path, _on_changes: OnChanges) -> io::Result<Box<dyn VfsWatcher>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} can't be watched", self.url),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_multistatus, WebDavBackend};
+    use crate::{
+        vfs::{RemoteUrl, VfsBackend},
+        FileType,
+    };
+    use std::{env, io::Read, path::Path, path::PathBuf};
+
+    #[test]
+    fn passwords_are_not_sent_over_plain_http() {
+        let (plain, _) = RemoteUrl::parse(Path::new("dav://me@example.com/")).unwrap();
+        let (anonymous, _) = RemoteUrl::parse(Path::new("dav://example.com/")).unwrap();
+
+        assert!(WebDavBackend::connect(&plain).is_err());
+        assert!(WebDavBackend::connect(&anonymous).is_ok());
+    }
+
+    #[test]
+    fn propfind_answers_are_parsed() {
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns">
+  <d:response>
+    <d:href>/remote.php/dav/files/me/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://cloud.example.com/remote.php/dav/files/me/My%20Notes.md</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getcontentlength>42</d:getcontentlength>
+        <d:getlastmodified>Sun, 06 Nov 1994 08:49:37 GMT</d:getlastmodified>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop><oc:size/></d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+        let entries = parse_multistatus(xml).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, PathBuf::from("/remote.php/dav/files/me"));
+        assert_eq!(entries[0].1.filetype, FileType::Folder);
+        assert_eq!(
+            entries[1].0,
+            PathBuf::from("/remote.php/dav/files/me/My Notes.md")
+        );
+        assert_eq!(entries[1].1.filetype, FileType::File);
+        assert_eq!((entries[1].1.size, entries[1].1.modified), (42, 784111777));
+    }
+
+    /// Needs a server, for example `dufs -A -p 5000 /tmp/dav` with
+    /// `MIELIKKI_DAV_URL=dav://localhost:5000/`
+    #[test]
+    #[ignore]
+    fn files_are_managed_over_webdav() {
+        let remote = env::var("MIELIKKI_DAV_URL").unwrap();
+        let (url, _) = RemoteUrl::parse(Path::new(&remote)).unwrap();
+        let backend = WebDavBackend::connect(&url).unwrap();
+        let root = backend
+            .canonicalize(Path::new(&remote))
+            .unwrap()
+            .join("mielikki_dav_test");
+
+        backend.create(&root, FileType::Folder).unwrap();
+        backend
+            .store(&root.join("notes.txt"), &mut "hello".as_bytes())
+            .unwrap();
+        assert!(backend
+            .create(&root.join("notes.txt"), FileType::File)
+            .is_err());
+        backend
+            .copy_inside(&root.join("notes.txt"), &root.join("copy.txt"))
+            .unwrap();
+        backend
+            .rename(&root.join("copy.txt"), &root.join("renamed.txt"))
+            .unwrap();
+        let mut names = backend
+            .list(&root)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>();
+        names.sort();
+        let mut contents = String::new();
+        backend
+            .open(&root.join("renamed.txt"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert!(backend.remove(&root).is_err());
+        backend.remove(&root.join("notes.txt")).unwrap();
+        backend.remove(&root.join("renamed.txt")).unwrap();
+        backend.remove(&root).unwrap();
+
+        assert_eq!(
+            names,
+            vec![root.join("notes.txt"), root.join("renamed.txt")]
+        );
+        assert_eq!(contents, "hello");
+        assert!(backend.stat(&root).is_err());
+    }
+}
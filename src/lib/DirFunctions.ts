@@ -1,11 +1,20 @@
 import { invoke } from '@tauri-apps/api/tauri';
-import directoryStore, { popForward, pushForward, clearSiblings } from './stores/DirectoryStore';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import { open } from '@tauri-apps/api/shell';
+import { get } from 'svelte/store';
+import { tick } from 'svelte';
+import tabStore from './stores/TabStore';
+import listingStore from './stores/ListingStore';
+import directoryStore, { clearSiblings, setHistory, setSelected } from './stores/DirectoryStore';
 import { addData } from './stores/SearchStore';
 
 export type Filedata = {
     name: string;
     path: string;
     filetype: Filetype;
+    mime?: string;
+    size?: number;
+    modified?: number;
 };
 
 export type Filetype = "File" | "Folder" | "Link";
@@ -16,6 +25,26 @@ export type FolderData = {
     is_at_root: boolean;
 };
 
+export type FolderWindow = {
+    name: string;
+    total: number;
+    offset: number;
+    files: Filedata[];
+    is_at_root: boolean;
+};
+
+export type HistoryEntry = {
+    path: string;
+    selected: string | null;
+    scroll: number;
+};
+
+export type History = {
+    back: HistoryEntry[];
+    current: HistoryEntry;
+    forward: HistoryEntry[];
+};
+
 export type SearchData = {
     name: string;
     files: boolean;
@@ -24,16 +53,20 @@ export type SearchData = {
     exact: boolean;
 };
 
-export const updateCurrentDir = (): void => {
+const tabId = (): number => get(tabStore).current;
+
+export const updateCurrentDir = (): Promise<void> => {
     clearSiblings();
 
-    invoke('get_current_folder', {})
+    const listing = invoke('get_current_folder', { tabId: tabId(), options: get(listingStore) })
         .then((data: FolderData) => {
             directoryStore.update((current) => {
                 return {
                     dirName: data.name,
-                    forward: current.forward,
+                    canGoBack: current.canGoBack,
+                    canGoForward: current.canGoForward,
                     isAtRoot: data.is_at_root,
+                    selected: current.selected,
                     siblings: data.files
                 };
             });
@@ -41,39 +74,265 @@ export const updateCurrentDir = (): void => {
         .catch(() => {
             changeToParentDirectory();
         });
+
+    invoke('get_history', { tabId: tabId() }).then((history: History) => {
+        setHistory(history.back.length > 0, history.forward.length > 0);
+    });
+    return listing;
+};
+
+// Remembers where the user was in the current folder before leaving it
+const saveCurrentView = (): Promise<void> => {
+    return saveViewState(get(directoryStore).selected, window.scrollY);
+};
+
+// Selects and scrolls back to where the user was when they left the folder
+const restoreView = (entry: HistoryEntry) => {
+    setSelected(entry.selected);
+    updateCurrentDir().then(() => tick()).then(() => window.scrollTo(0, entry.scroll));
 };
 
 export const changeDirectory = (path: string, toParent = false) => {
-    invoke('move_to_folder', { folderPath: path, toParent })
+    saveCurrentView()
+        .then(() => invoke('move_to_folder', { tabId: tabId(), folderPath: path, toParent }))
         .then(() => {
+            setSelected(null);
             updateCurrentDir();
         })
         .catch((err) => console.error(err));
 };
 
+// Opens the file in its default app and counts it for jumping
+export const openFile = (path: string) => {
+    open(path).catch((err) => console.error(err));
+    invoke('record_file_open', { path }).catch((err) => console.error(err));
+};
+
 export const changeToParentDirectory = () => {
-    invoke('move_to_parent_folder')
-        .then((path: string) => {
+    saveCurrentView()
+        .then(() => invoke('move_to_parent_folder', { tabId: tabId() }))
+        .then(() => {
+            setSelected(null);
             updateCurrentDir();
-            pushForward(path);
         })
         .catch((err) => console.error(err));
 };
 
 export const searchFiles = (data: SearchData) => {
-    invoke('find_file', data).then((results: Filedata[]) => {
+    invoke('find_file', { tabId: tabId(), ...data }).then((results: Filedata[]) => {
         addData(results);
     });
 };
 
+export const moveBackDir = () => {
+    saveCurrentView()
+        .then(() => invoke('go_back', { tabId: tabId() }))
+        .then((entry: HistoryEntry) => restoreView(entry))
+        .catch((err) => console.error(err));
+};
+
 export const moveForwardDir = () => {
-    const newDir = popForward();
-    changeDirectory(newDir);
+    saveCurrentView()
+        .then(() => invoke('go_forward', { tabId: tabId() }))
+        .then((entry: HistoryEntry) => restoreView(entry))
+        .catch((err) => console.error(err));
+};
+
+// Only the visible rows of huge folders are fetched
+export const getFolderWindow = (offset: number, limit: number): Promise<FolderWindow> => {
+    return invoke('get_folder_window', {
+        tabId: tabId(),
+        options: get(listingStore),
+        offset,
+        limit
+    });
+};
+
+export type FolderChanges = {
+    tab_id: number;
+    changes: { kind: 'Created' | 'Removed' | 'Renamed' | 'Modified' }[];
+};
+
+// Reloads the listing whenever the folder of the current tab changes
+export const watchCurrentFolder = (): Promise<UnlistenFn> => {
+    invoke('watch_folder', { tabId: tabId() });
+    return listen<FolderChanges>('folder-changed', (event) => {
+        if (event.payload.tab_id === tabId()) {
+            updateCurrentDir();
+        }
+    });
+};
+
+// Indexing runs in the background, so its failures only show up here
+export const watchIndexingErrors = (): Promise<UnlistenFn> => {
+    return listen<string>('indexing-failed', (event) => {
+        console.error('Indexing files failed:', event.payload);
+    });
+};
+
+export const saveViewState = (selected: string | null, scroll: number): Promise<void> => {
+    return invoke('save_view_state', { tabId: tabId(), selected, scroll });
 };
 
 export const createFile = (filename: string, filetype: Filetype) => {
-    invoke('create_file', {filename, filetype}).then(() => {
+    invoke('create_file', { tabId: tabId(), filename, filetype }).then(() => {
         updateCurrentDir()
     })
 }
 
+export const renameFile = (filename: string, newName: string) => {
+    invoke('rename_file', { tabId: tabId(), filename, newName }).then(() => {
+        updateCurrentDir()
+    })
+}
+
+/** The destination can also be a remote folder such as sftp://user@host/path */
+export const copyFiles = (filenames: string[], destination: string) => {
+    return invoke('copy_files', { tabId: tabId(), filenames, destination })
+}
+
+/** Kept in the secret store of the desktop, for urls such as davs://user@host */
+export const saveRemotePassword = (url: string, password: string) => {
+    return invoke('save_remote_password', { url, password })
+}
+
+export type Volume = {
+    device: string,
+    mount_point: string,
+    filesystem: string,
+    label: string | null,
+    uuid: string | null,
+    total: number | null,
+    free: number | null,
+    used: number | null,
+    removable: boolean,
+    read_only: boolean,
+}
+
+export const listVolumes = (): Promise<Volume[]> => {
+    return invoke('list_volumes')
+}
+
+export type UsageEntry = {
+    name: string,
+    path: string,
+    filetype: Filetype,
+    size: number,
+    items: number,
+}
+
+export type FolderUsage = {
+    path: string,
+    size: number,
+    items: number,
+    scanned: number,
+    children: UsageEntry[],
+}
+
+export type UsageProgress = {
+    job_id: number,
+    size: number,
+    items: number,
+    children: UsageEntry[],
+    current: string | null,
+}
+
+/** Returns the job id, partial results arrive as "disk-usage-progress" events */
+export const analyzeDiskUsage = (path: string): Promise<number> => {
+    return invoke('analyze_disk_usage', { path })
+}
+
+/** The saved scan of the current folder, null when it hasn't been analyzed */
+export const getDiskUsage = (): Promise<FolderUsage | null> => {
+    return invoke('get_disk_usage', { tabId: tabId() })
+}
+
+export type DuplicateGroup = {
+    size: number,
+    hash: string,
+    paths: string[],
+    reclaimable: number,
+}
+
+export type Duplicates = {
+    job_id: number,
+    groups: DuplicateGroup[],
+    reclaimable: number,
+}
+
+/** Returns the job id, the groups arrive as a "duplicates-found" event */
+export const findDuplicates = (roots: string[], hardLinksAreSame = false): Promise<number> => {
+    return invoke('find_duplicates', { roots, options: { hard_links_are_same: hardLinksAreSame } })
+}
+
+export type HashAlgorithm = 'Md5' | 'Sha1' | 'Sha256' | 'Blake3'
+
+export type Checksums = {
+    job_id: number,
+    files: {
+        path: string,
+        hashes: Partial<Record<HashAlgorithm, string>>,
+        /** Why the entry wasn't hashed, such as it being a folder */
+        error: string | null,
+    }[],
+}
+
+export type ManifestCheck = {
+    job_id: number,
+    manifest: string,
+    algorithm: HashAlgorithm,
+    files: {
+        path: string,
+        expected: string,
+        actual: string | null,
+        status: 'Ok' | 'Failed' | 'Missing',
+    }[],
+    failed: number,
+    missing: number,
+}
+
+/** Returns the job id, the hashes arrive as a "checksums-computed" event */
+export const computeChecksums = (paths: string[], algorithms: HashAlgorithm[]): Promise<number> => {
+    return invoke('compute_checksums', { paths, algorithms })
+}
+
+/** Returns the job id, the results arrive as a "manifest-verified" event */
+export const verifyManifest = (manifest: string): Promise<number> => {
+    return invoke('verify_manifest', { manifest })
+}
+
+/** The manifest is written into the folder, its path comes with "job-finished" */
+export const generateManifest = (folder: string, algorithm: HashAlgorithm): Promise<number> => {
+    return invoke('generate_manifest', { folder, algorithm })
+}
+
+export type CompareMode = 'SizeAndModified' | 'Contents'
+
+export type ComparedSide = {
+    filetype: Filetype,
+    size: number,
+    modified: number,
+}
+
+export type ComparedEntry = {
+    name: string,
+    path: string,
+    left: ComparedSide | null,
+    right: ComparedSide | null,
+    difference: 'OnlyLeft' | 'OnlyRight' | 'Identical' | 'Different',
+    children: ComparedEntry[],
+}
+
+export type Comparison = {
+    job_id: number,
+    left: string,
+    right: string,
+    mode: CompareMode,
+    summary: { only_left: number, only_right: number, identical: number, different: number },
+    entries: ComparedEntry[],
+}
+
+/** Returns the job id, the tree arrives as a "folders-compared" event */
+export const compareFolders = (left: string, right: string, mode: CompareMode = 'SizeAndModified'): Promise<number> => {
+    return invoke('compare_folders', { left, right, mode })
+}
@@ -3,14 +3,18 @@ import type { Filedata } from '$lib/DirFunctions';
 
 type DirectoryStore = Writable<{
     dirName: string;
-    forward: string[];
+    canGoBack: boolean;
+    canGoForward: boolean;
     siblings: Filedata[];
     isAtRoot: boolean;
+    selected: string | null;
 }>;
 const directoryStore: DirectoryStore = writable({
     dirName: '',
-    forward: [],
+    canGoBack: false,
+    canGoForward: false,
     isAtRoot: false,
+    selected: null,
     siblings: [
         {
             name: '',
@@ -20,49 +24,41 @@ const directoryStore: DirectoryStore = writable({
     ]
 });
 
-export const pushForward = (new_path: string) => {
+export const setHistory = (canGoBack: boolean, canGoForward: boolean) => {
     directoryStore.update((current) => {
         return {
             dirName: current.dirName,
             siblings: current.siblings,
             isAtRoot: current.isAtRoot,
-            forward: [...current.forward, new_path]
+            selected: current.selected,
+            canGoBack,
+            canGoForward
         };
     });
 };
 
-export const popForward = (): string => {
-    let last = '';
-    directoryStore.update((current) => {
-        last = current.forward[current.forward.length - 1];
-        return {
-            dirName: current.dirName,
-            siblings: current.siblings,
-            isAtRoot: current.isAtRoot,
-            forward: [...current.forward.slice(0, current.forward.length - 1)]
-        };
-    });
-    return last;
-};
-
-export const clearForward = () => {
+export const clearSiblings = () => {
     directoryStore.update((current) => {
         return {
             dirName: current.dirName,
-            siblings: current.siblings,
+            canGoBack: current.canGoBack,
+            canGoForward: current.canGoForward,
             isAtRoot: current.isAtRoot,
-            forward: []
+            selected: current.selected,
+            siblings: []
         };
     });
 };
 
-export const clearSiblings = () => {
+export const setSelected = (selected: string | null) => {
     directoryStore.update((current) => {
         return {
             dirName: current.dirName,
-            forward: current.forward,
+            canGoBack: current.canGoBack,
+            canGoForward: current.canGoForward,
             isAtRoot: current.isAtRoot,
-            siblings: []
+            siblings: current.siblings,
+            selected
         };
     });
 };
@@ -0,0 +1,18 @@
+import { writable, type Writable } from 'svelte/store';
+
+export type ListingOptions = {
+    show_hidden: boolean;
+    show_backups: boolean;
+    show_ignored: boolean;
+    filter: string | null;
+};
+
+// Same defaults as the backend
+const listingStore: Writable<ListingOptions> = writable({
+    show_hidden: false,
+    show_backups: false,
+    show_ignored: true,
+    filter: null
+});
+
+export default listingStore;
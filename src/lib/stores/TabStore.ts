@@ -0,0 +1,14 @@
+import { writable, type Writable } from 'svelte/store';
+
+type TabStore = Writable<{
+    current: number;
+    tabs: number[];
+}>;
+
+// The backend opens the first tab with id 0 on startup
+const tabStore: TabStore = writable({
+    current: 0,
+    tabs: [0]
+});
+
+export default tabStore;